@@ -0,0 +1,296 @@
+use image::{DynamicImage, GrayImage, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    glare::glare_mask,
+    imaging::{Mask, Moments},
+    photo_set::MoleMetrics,
+    segmentation::{metrics_mask, segmentation_image},
+};
+
+pub const COLOURS_MAX: u8 = 6;
+pub const STRUCTURES_MAX: u8 = 5;
+
+/// Total Dermoscopy Score above which a lesion is suspicious.
+pub const TDS_SUSPICIOUS: f32 = 4.75;
+/// Total Dermoscopy Score above which a lesion is highly suspicious for melanoma.
+pub const TDS_MALIGNANT: f32 = 5.45;
+
+/// Fraction of the lesion that may not overlap its mirror image for a symmetric axis.
+const SHAPE_ASYMMETRY: f32 = 0.12;
+/// Distance between the mean colours of the two halves for a symmetric axis.
+const COLOUR_ASYMMETRY: f32 = 25.0;
+/// Edge gradient, relative to the lesion contrast, of an abrupt border segment.
+const ABRUPT_BORDER: f32 = 0.25;
+/// Fraction of the lesion a colour must cover to be counted.
+const COLOUR_PRESENCE: f32 = 0.05;
+
+/// Reference colours of the ABCD rule: white, red, light brown, dark brown, blue-gray, black.
+const REFERENCE_COLOURS: [[f32; 3]; 6] = [
+    [235.0, 235.0, 235.0],
+    [200.0, 60.0, 60.0],
+    [180.0, 120.0, 80.0],
+    [100.0, 60.0, 40.0],
+    [110.0, 130.0, 150.0],
+    [35.0, 30.0, 30.0],
+];
+
+/// Components of the ABCD rule of dermoscopy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct AbcdScore {
+    /// Number of principal axes the lesion is asymmetric about, 0 to 2.
+    pub asymmetry: u8,
+    /// Number of the eight border segments with an abrupt cut-off, 0 to 8.
+    pub border: u8,
+    /// Number of colours present, 1 to 6.
+    pub colours: u8,
+    /// Number of differential structures, 0 to 5; entered by the user.
+    pub structures: u8,
+}
+
+impl AbcdScore {
+    /// The Total Dermoscopy Score.
+    pub fn total(&self) -> f32 {
+        1.3 * self.asymmetry as f32
+            + 0.1 * self.border as f32
+            + 0.5 * self.colours as f32
+            + 0.5 * self.structures as f32
+    }
+
+    pub fn assessment(&self) -> &'static str {
+        let total = self.total();
+        if total > TDS_MALIGNANT {
+            "highly suspicious"
+        } else if total >= TDS_SUSPICIOUS {
+            "suspicious"
+        } else {
+            "benign"
+        }
+    }
+}
+
+impl std::fmt::Display for AbcdScore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "TDS {:.2} (A{} B{} C{} D{})",
+            self.total(),
+            self.asymmetry,
+            self.border,
+            self.colours,
+            self.structures
+        ))
+    }
+}
+
+/// Scores the lesion outlined by `metrics`; the differential structures cannot be
+/// detected automatically and are taken from `structures`.
+pub fn compute_abcd(
+    image: &DynamicImage,
+    metrics: &MoleMetrics,
+    px_per_mm: f32,
+    structures: u8,
+) -> Option<AbcdScore> {
+    let small = segmentation_image(image);
+    let scale = px_per_mm * small.width() as f32 / image.width() as f32;
+    let lesion = metrics_mask(metrics, small.width(), small.height(), scale);
+    score_lesion(&small, &lesion, structures)
+}
+
+/// Scores a lesion given as a mask of a photo. Glare is left out of the colour
+/// statistics, where it would count as white.
+pub fn score_lesion(image: &RgbImage, lesion: &Mask, structures: u8) -> Option<AbcdScore> {
+    let moments = Moments::new(lesion)?;
+    let glare = glare_mask(image);
+    Some(AbcdScore {
+        asymmetry: asymmetry(image, lesion, &glare, &moments),
+        border: border(image, lesion, &moments),
+        colours: colours(image, lesion, &glare),
+        structures: structures.min(STRUCTURES_MAX),
+    })
+}
+
+fn asymmetry(image: &RgbImage, lesion: &Mask, glare: &Mask, moments: &Moments) -> u8 {
+    let pixels = lesion.pixels();
+    let mut asymmetric_axes = 0;
+    for angle in [moments.angle, moments.angle + std::f32::consts::FRAC_PI_2] {
+        let (sin, cos) = angle.sin_cos();
+        let mut mismatched = 0;
+        let mut halves = [([0.0f32; 3], 0usize); 2];
+        for (x, y) in pixels.iter() {
+            let dx = *x as f32 + 0.5 - moments.cx;
+            let dy = *y as f32 + 0.5 - moments.cy;
+            let along = dx * cos + dy * sin;
+            let across = -dx * sin + dy * cos;
+            // Mirror across the axis: keep the component along it, flip the other.
+            let mx = moments.cx + along * cos + across * sin;
+            let my = moments.cy + along * sin - across * cos;
+            if !lesion.get_signed(mx.floor() as i64, my.floor() as i64) {
+                mismatched += 1;
+            }
+
+            if glare.get(*x, *y) {
+                continue;
+            }
+            let half = &mut halves[if across >= 0.0 { 0 } else { 1 }];
+            let pixel = image.get_pixel(*x, *y);
+            for channel in 0..3 {
+                half.0[channel] += pixel[channel] as f32;
+            }
+            half.1 += 1;
+        }
+
+        let shape_asymmetric = mismatched as f32 / moments.area > SHAPE_ASYMMETRY;
+        let colour_asymmetric = halves.iter().all(|(_, count)| *count > 0)
+            && colour_distance(
+                mean_colour(&halves[0].0, halves[0].1),
+                mean_colour(&halves[1].0, halves[1].1),
+            ) > COLOUR_ASYMMETRY;
+        if shape_asymmetric || colour_asymmetric {
+            asymmetric_axes += 1;
+        }
+    }
+    asymmetric_axes
+}
+
+fn border(image: &RgbImage, lesion: &Mask, moments: &Moments) -> u8 {
+    let (width, height) = image.dimensions();
+    let luminance = image::imageops::blur(
+        &GrayImage::from_fn(width, height, |x, y| {
+            let pixel = image.get_pixel(x, y);
+            image::Luma([
+                ((pixel[0] as u32 * 3 + pixel[1] as u32 * 6 + pixel[2] as u32) / 10) as u8,
+            ])
+        }),
+        1.0,
+    );
+    let value = |x: i64, y: i64| {
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        luminance.get_pixel(x, y)[0] as f32
+    };
+
+    let surrounding = lesion.dilate(3);
+    let (mut lesion_sum, mut lesion_count, mut skin_sum, mut skin_count) = (0.0, 0, 0.0, 0);
+    for y in 0..height {
+        for x in 0..width {
+            if lesion.get(x, y) {
+                lesion_sum += value(x as i64, y as i64);
+                lesion_count += 1;
+            } else if surrounding.get(x, y) {
+                skin_sum += value(x as i64, y as i64);
+                skin_count += 1;
+            }
+        }
+    }
+    if lesion_count == 0 || skin_count == 0 {
+        return 0;
+    }
+    let contrast = (skin_sum / skin_count as f32 - lesion_sum / lesion_count as f32)
+        .abs()
+        .max(1.0);
+
+    let mut segments = [(0.0f32, 0usize); 8];
+    for (x, y) in lesion.pixels() {
+        let (sx, sy) = (x as i64, y as i64);
+        let on_border = !lesion.get_signed(sx - 1, sy)
+            || !lesion.get_signed(sx + 1, sy)
+            || !lesion.get_signed(sx, sy - 1)
+            || !lesion.get_signed(sx, sy + 1);
+        if !on_border {
+            continue;
+        }
+        let gx = (value(sx + 1, sy) - value(sx - 1, sy)) / 2.0;
+        let gy = (value(sx, sy + 1) - value(sx, sy - 1)) / 2.0;
+        let angle = (y as f32 + 0.5 - moments.cy).atan2(x as f32 + 0.5 - moments.cx);
+        let segment = (((angle / std::f32::consts::TAU + 1.0) * 8.0) as usize) % 8;
+        segments[segment].0 += (gx * gx + gy * gy).sqrt();
+        segments[segment].1 += 1;
+    }
+
+    segments
+        .iter()
+        .filter(|(sum, count)| *count > 0 && sum / *count as f32 / contrast > ABRUPT_BORDER)
+        .count() as u8
+}
+
+fn colours(image: &RgbImage, lesion: &Mask, glare: &Mask) -> u8 {
+    let mut counts = [0usize; 6];
+    let pixels: Vec<(u32, u32)> = lesion
+        .pixels()
+        .into_iter()
+        .filter(|(x, y)| !glare.get(*x, *y))
+        .collect();
+    for (x, y) in pixels.iter() {
+        let pixel = image.get_pixel(*x, *y);
+        let colour = [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32];
+        let nearest = (0..REFERENCE_COLOURS.len())
+            .min_by(|a, b| {
+                colour_distance(colour, REFERENCE_COLOURS[*a])
+                    .partial_cmp(&colour_distance(colour, REFERENCE_COLOURS[*b]))
+                    .unwrap()
+            })
+            .unwrap();
+        counts[nearest] += 1;
+    }
+    let present = counts
+        .iter()
+        .filter(|count| **count as f32 >= pixels.len() as f32 * COLOUR_PRESENCE)
+        .count() as u8;
+    present.clamp(1, COLOURS_MAX)
+}
+
+fn mean_colour(sum: &[f32; 3], count: usize) -> [f32; 3] {
+    [
+        sum[0] / count as f32,
+        sum[1] / count as f32,
+        sum[2] / count as f32,
+    ]
+}
+
+fn colour_distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+#[test]
+fn test_abcd_total() {
+    let score = AbcdScore {
+        asymmetry: 2,
+        border: 4,
+        colours: 3,
+        structures: 2,
+    };
+    assert!((score.total() - 5.5).abs() < 1e-5);
+    assert_eq!(score.assessment(), "highly suspicious");
+    assert_eq!(AbcdScore::default().assessment(), "benign");
+}
+
+#[test]
+fn test_score_round_lesion() {
+    let image = crate::fixtures::lesion_image(128, 128, (64.0, 64.0), 30.0);
+    let lesion = Mask::from_fn(128, 128, |x, y| image.get_pixel(x, y)[0] < 150);
+    let score = score_lesion(&image, &lesion, 1).unwrap();
+    assert_eq!(score.asymmetry, 0);
+    assert_eq!(score.border, 8);
+    assert_eq!(score.colours, 1);
+    assert_eq!(score.structures, 1);
+}
+
+#[test]
+fn test_score_asymmetric_lesion() {
+    // Half dark brown, half black, on a blurry oval.
+    let image = RgbImage::from_fn(128, 128, |x, y| {
+        let d = ((x as f32 - 64.0) / 40.0).powi(2) + ((y as f32 - 64.0) / 25.0).powi(2);
+        if d > 1.0 {
+            image::Rgb([220, 180, 160])
+        } else if x < 64 {
+            image::Rgb([100, 60, 40])
+        } else {
+            image::Rgb([30, 25, 25])
+        }
+    });
+    let lesion = Mask::from_fn(128, 128, |x, y| image.get_pixel(x, y)[0] < 150);
+    let score = score_lesion(&image, &lesion, 0).unwrap();
+    assert_eq!(score.asymmetry, 1);
+    assert_eq!(score.colours, 2);
+}
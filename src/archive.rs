@@ -0,0 +1,356 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    errors::{DScopeError, DScopeResult},
+    mole::{next_mole_id, Mole, MoleId, MoleObservation},
+    photo_set::{write_file_atomically, PhotoSet, PhotoSetData},
+};
+
+const PATIENT_FILE_NAME: &str = "patient.json";
+const PATIENT_ID_PREFIX: &str = "P";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatientInfo {
+    pub id: String,
+    pub name: String,
+    pub surname: String,
+    pub notes: String,
+    #[serde(default)]
+    pub moles: Vec<Mole>,
+}
+
+impl PatientInfo {
+    pub fn display_name(&self) -> String {
+        match (self.surname.len(), self.name.len()) {
+            (0, 0) => self.id.clone(),
+            (_, 0) => self.surname.clone(),
+            (0, _) => self.name.clone(),
+            _ => format!("{} {}", self.surname, self.name),
+        }
+    }
+
+    pub fn mole(&self, id: MoleId) -> Option<&Mole> {
+        self.moles.iter().find(|mole| mole.id == id)
+    }
+
+    pub fn add_mole(&mut self, label: String, location: String) -> MoleId {
+        let id = next_mole_id(&self.moles);
+        self.moles.push(Mole {
+            id,
+            label,
+            location,
+            marker: None,
+        });
+        id
+    }
+}
+
+pub struct Visit {
+    pub path: PathBuf,
+    pub time: SystemTime,
+    pub notes: String,
+    pub photo_count: usize,
+}
+
+pub struct Patient {
+    pub path: PathBuf,
+    pub info: PatientInfo,
+    /// Visits sorted chronologically, oldest first.
+    pub visits: Vec<Visit>,
+    /// Why the visits left out of `visits` could not be read.
+    pub unreadable_visits: Vec<DScopeError>,
+}
+
+impl Patient {
+    pub fn from_path(path: PathBuf) -> DScopeResult<Self> {
+        let mut info_path = path.clone();
+        info_path.push(PATIENT_FILE_NAME);
+        let info_text = std::fs::read_to_string(&info_path).map_err(|error| {
+            DScopeError::cannot_read_file(error, info_path.to_string_lossy().to_string())
+        })?;
+        let info = serde_json::from_str(&info_text).map_err(|error| {
+            DScopeError::cannot_decode_info(error, info_path.to_string_lossy().to_string())
+        })?;
+
+        let mut patient = Self {
+            path,
+            info,
+            visits: Vec::new(),
+            unreadable_visits: Vec::new(),
+        };
+        patient.reload_visits()?;
+        Ok(patient)
+    }
+
+    pub fn create(archive_path: &Path, name: String, surname: String) -> DScopeResult<Self> {
+        let (id, path) = new_patient_id(archive_path);
+        std::fs::create_dir_all(&path).map_err(|error| {
+            DScopeError::cannot_create_directory(error, path.to_string_lossy().to_string())
+        })?;
+
+        let patient = Self {
+            path,
+            info: PatientInfo {
+                id,
+                name,
+                surname,
+                notes: String::new(),
+                moles: Vec::new(),
+            },
+            visits: Vec::new(),
+            unreadable_visits: Vec::new(),
+        };
+        patient.save()?;
+        Ok(patient)
+    }
+
+    pub fn save(&self) -> DScopeResult<()> {
+        let data = serde_json::to_vec_pretty(&self.info).unwrap();
+        let mut info_path = self.path.clone();
+        info_path.push(PATIENT_FILE_NAME);
+        write_file_atomically(&info_path, &data)
+    }
+
+    /// Lists the visits of the patient, leaving out those that cannot be read.
+    pub fn reload_visits(&mut self) -> DScopeResult<()> {
+        let entries = self.path.read_dir().map_err(|error| {
+            DScopeError::cannot_read_file(error, self.path.to_string_lossy().to_string())
+        })?;
+
+        let mut visits = Vec::new();
+        let mut unreadable_visits = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !path.is_dir() {
+                continue;
+            }
+            match PhotoSetData::read(&path) {
+                Ok(Some((data, _))) => visits.push(Visit {
+                    path,
+                    time: data.time,
+                    notes: data.notes,
+                    photo_count: data.photos.len(),
+                }),
+                Ok(None) => {}
+                Err(error) => unreadable_visits.push(error),
+            }
+        }
+        visits.sort_by_key(|visit| visit.time);
+
+        self.visits = visits;
+        self.unreadable_visits = unreadable_visits;
+        Ok(())
+    }
+
+    /// Collects every saved photo of the mole across all visits, oldest first, leaving out
+    /// visits that can no longer be read.
+    pub fn mole_history(&self, mole_id: MoleId) -> Vec<MoleObservation> {
+        let mut observations = Vec::new();
+        for visit in self.visits.iter() {
+            let data = match PhotoSetData::read(&visit.path) {
+                Ok(Some((data, _))) => data,
+                _ => continue,
+            };
+            for (photo_id, info) in data.photos {
+                if info.mole_id == Some(mole_id) {
+                    observations.push(MoleObservation {
+                        visit_path: visit.path.clone(),
+                        photo_id,
+                        info,
+                        calibration: data.calibration.clone(),
+                        colour_matrix: data.colour_matrix,
+                    });
+                }
+            }
+        }
+        observations.sort_by_key(|observation| (observation.info.time, observation.photo_id));
+        observations
+    }
+
+    /// Moves the visit into a new folder of this patient and links it to the patient record.
+    ///
+    /// On success returns the folder the visit was loaded from and the files kept by the
+    /// save, so that the caller can offer to clean it up like "Save as" does.
+    pub fn add_visit(&mut self, photos: &mut PhotoSet) -> DScopeResult<(PathBuf, Vec<String>)> {
+        let path = self.new_visit_path(photos.info.time);
+        std::fs::create_dir_all(&path).map_err(|error| {
+            DScopeError::cannot_create_directory(error, path.to_string_lossy().to_string())
+        })?;
+
+        let old_path = photos.path.clone();
+        let old_info = photos.info.clone();
+        photos.path = path;
+        photos.info.patient_id = Some(self.info.id.clone());
+        photos.info.name = self.info.name.clone();
+        photos.info.surname = self.info.surname.clone();
+        let kept = match photos.save() {
+            Ok(kept) => kept,
+            Err(error) => {
+                photos.path = old_path;
+                photos.info = old_info;
+                return Err(error);
+            }
+        };
+
+        self.reload_visits()?;
+        Ok((old_path, kept))
+    }
+
+    fn new_visit_path(&self, time: SystemTime) -> PathBuf {
+        let secs = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() as i64)
+            .unwrap_or(0);
+        let time = chrono::NaiveDateTime::from_timestamp(secs, 0);
+        let base_name = time.format("%Y-%m-%d_%H%M").to_string();
+
+        let mut path = self.path.clone();
+        path.push(&base_name);
+        let mut counter = 1;
+        while path.exists() {
+            counter += 1;
+            path.set_file_name(format!("{}_{}", base_name, counter));
+        }
+        path
+    }
+}
+
+pub struct Archive {
+    pub path: PathBuf,
+    pub patients: Vec<Patient>,
+    /// Why the patients left out of `patients` could not be read.
+    pub unreadable_patients: Vec<DScopeError>,
+}
+
+impl Archive {
+    pub fn from_path(path: PathBuf) -> DScopeResult<Self> {
+        if !path.is_dir() {
+            return Err(DScopeError::expected_directory(
+                path.to_string_lossy().to_string(),
+            ));
+        }
+
+        let entries = path.read_dir().map_err(|error| {
+            DScopeError::cannot_read_file(error, path.to_string_lossy().to_string())
+        })?;
+
+        let mut patients = Vec::new();
+        let mut unreadable_patients = Vec::new();
+        for entry in entries.flatten() {
+            let patient_path = entry.path();
+            let mut info_path = patient_path.clone();
+            info_path.push(PATIENT_FILE_NAME);
+            if info_path.is_file() {
+                match Patient::from_path(patient_path) {
+                    Ok(patient) => patients.push(patient),
+                    Err(error) => unreadable_patients.push(error),
+                }
+            }
+        }
+
+        let mut archive = Self {
+            path,
+            patients,
+            unreadable_patients,
+        };
+        archive.sort_patients();
+        Ok(archive)
+    }
+
+    pub fn add_patient(&mut self, name: String, surname: String) -> DScopeResult<usize> {
+        let patient = Patient::create(&self.path, name, surname)?;
+        let id = patient.info.id.clone();
+        self.patients.push(patient);
+        self.sort_patients();
+        Ok(self.patient_index(&id).unwrap())
+    }
+
+    pub fn patient_index(&self, id: &str) -> Option<usize> {
+        self.patients.iter().position(|p| p.info.id == id)
+    }
+
+    fn sort_patients(&mut self) {
+        self.patients.sort_by(|a, b| {
+            (&a.info.surname, &a.info.name, &a.info.id).cmp(&(
+                &b.info.surname,
+                &b.info.name,
+                &b.info.id,
+            ))
+        });
+    }
+}
+
+/// Picks an unused patient id in the archive, together with the folder of the new patient.
+///
+/// Ids are derived from the creation time so that they never need renumbering.
+fn new_patient_id(archive_path: &Path) -> (String, PathBuf) {
+    let mut stamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    loop {
+        let id = format!("{}{}", PATIENT_ID_PREFIX, stamp);
+        let mut path = archive_path.to_path_buf();
+        path.push(&id);
+        if !path.exists() {
+            return (id, path);
+        }
+        stamp += 1;
+    }
+}
+
+#[test]
+fn test_patient_display_name() {
+    let mut info = PatientInfo {
+        id: "P42".to_string(),
+        name: String::new(),
+        surname: String::new(),
+        notes: String::new(),
+        moles: Vec::new(),
+    };
+    assert_eq!(&info.display_name(), "P42");
+    info.surname = "Rossi".to_string();
+    assert_eq!(&info.display_name(), "Rossi");
+    info.name = "Mario".to_string();
+    assert_eq!(&info.display_name(), "Rossi Mario");
+}
+
+#[test]
+fn test_patient_add_mole() {
+    let mut info = PatientInfo {
+        id: "P42".to_string(),
+        name: String::new(),
+        surname: String::new(),
+        notes: String::new(),
+        moles: Vec::new(),
+    };
+    let first = info.add_mole("A".to_string(), "Left shoulder".to_string());
+    let second = info.add_mole("B".to_string(), "Back".to_string());
+    assert_ne!(first, second);
+    assert_eq!(info.mole(second).map(|mole| mole.label.as_str()), Some("B"));
+    assert_eq!(info.mole(second + 1), None);
+}
+
+#[test]
+fn test_archive_skips_unreadable_entries() {
+    let path = std::env::temp_dir().join(format!("d-scope-archive-{}", std::process::id()));
+    std::fs::create_dir_all(&path).unwrap();
+    let patient = Patient::create(&path, "Mario".to_string(), "Rossi".to_string()).unwrap();
+    std::fs::create_dir_all(patient.path.join("corrupt-visit")).unwrap();
+    std::fs::write(patient.path.join("corrupt-visit").join("info.json"), "{").unwrap();
+    std::fs::create_dir_all(path.join("corrupt-patient")).unwrap();
+    std::fs::write(path.join("corrupt-patient").join(PATIENT_FILE_NAME), "").unwrap();
+
+    let archive = Archive::from_path(path.clone());
+    std::fs::remove_dir_all(&path).unwrap();
+
+    let archive = archive.unwrap();
+    assert_eq!(archive.patients.len(), 1);
+    assert_eq!(archive.unreadable_patients.len(), 1);
+    assert!(archive.patients[0].visits.is_empty());
+    assert_eq!(archive.patients[0].unreadable_visits.len(), 1);
+}
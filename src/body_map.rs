@@ -0,0 +1,224 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+use crate::{
+    mole::{Mole, MoleId},
+    outline::Point,
+};
+
+/// Largest distance between a click and the marker it selects, in map units.
+const MARKER_RADIUS: f32 = 0.05;
+/// Points sampled along the ellipses of the silhouettes.
+const ELLIPSE_SAMPLES: usize = 32;
+
+/// Views of the body map, each with its own silhouette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyView {
+    Front,
+    Back,
+    Left,
+    Right,
+    Hands,
+    Feet,
+    Scalp,
+}
+
+impl BodyView {
+    pub const ALL: [BodyView; 7] = [
+        BodyView::Front,
+        BodyView::Back,
+        BodyView::Left,
+        BodyView::Right,
+        BodyView::Hands,
+        BodyView::Feet,
+        BodyView::Scalp,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BodyView::Front => "Front",
+            BodyView::Back => "Back",
+            BodyView::Left => "Left",
+            BodyView::Right => "Right",
+            BodyView::Hands => "Hands",
+            BodyView::Feet => "Feet",
+            BodyView::Scalp => "Scalp",
+        }
+    }
+
+    /// Closed outlines of the silhouette, in map units: the silhouette fits in the square
+    /// from -1 to 1, the Y axis upwards.
+    pub fn silhouette(&self) -> Vec<Vec<Point>> {
+        match self {
+            BodyView::Front | BodyView::Back => {
+                // The right half of the body, from the neck down to the crotch.
+                let half = [
+                    [0.05, 0.68],
+                    [0.22, 0.6],
+                    [0.28, 0.5],
+                    [0.33, 0.2],
+                    [0.36, -0.05],
+                    [0.38, -0.15],
+                    [0.33, -0.17],
+                    [0.29, -0.03],
+                    [0.26, 0.2],
+                    [0.2, 0.4],
+                    [0.19, 0.2],
+                    [0.16, 0.05],
+                    [0.2, -0.08],
+                    [0.18, -0.5],
+                    [0.15, -0.9],
+                    [0.17, -0.98],
+                    [0.06, -0.98],
+                    [0.07, -0.5],
+                    [0.0, -0.12],
+                ];
+                let mut body = half.to_vec();
+                body.extend(half.iter().rev().skip(1).map(|[x, y]| [-x, *y]));
+                vec![ellipse([0.0, 0.82], [0.11, 0.14]), body]
+            }
+            BodyView::Right => vec![
+                ellipse([0.02, 0.82], [0.1, 0.13]),
+                vec![
+                    [-0.05, 0.68],
+                    [-0.1, 0.55],
+                    [-0.11, 0.3],
+                    [-0.08, 0.1],
+                    [-0.12, -0.08],
+                    [-0.08, -0.5],
+                    [-0.07, -0.7],
+                    [-0.06, -0.98],
+                    [0.12, -0.98],
+                    [0.04, -0.9],
+                    [0.05, -0.5],
+                    [0.08, -0.1],
+                    [0.1, 0.1],
+                    [0.12, 0.4],
+                    [0.08, 0.6],
+                    [0.04, 0.68],
+                ],
+                ellipse([0.0, 0.25], [0.05, 0.33]),
+            ],
+            BodyView::Left => BodyView::Right
+                .silhouette()
+                .into_iter()
+                .map(|outline| outline.into_iter().map(|[x, y]| [-x, y]).collect())
+                .collect(),
+            BodyView::Hands => [-1.0, 1.0]
+                .iter()
+                .flat_map(|side| {
+                    let x = side * 0.45;
+                    let mut hand = vec![
+                        ellipse([x, -0.2], [0.17, 0.22]),
+                        ellipse([x - side * 0.21, -0.12], [0.04, 0.11]),
+                    ];
+                    for (offset, length) in
+                        [(-0.12, 0.12), (-0.04, 0.16), (0.04, 0.15), (0.12, 0.11)]
+                    {
+                        hand.push(ellipse([x + side * offset, length], [0.035, length]));
+                    }
+                    hand
+                })
+                .collect(),
+            BodyView::Feet => [-1.0, 1.0]
+                .iter()
+                .flat_map(|side| {
+                    let x = side * 0.3;
+                    let mut foot = vec![ellipse([x, -0.15], [0.15, 0.5])];
+                    for toe in 0..5 {
+                        let toe = toe as f32;
+                        foot.push(ellipse(
+                            [x - side * (0.1 - toe * 0.05), 0.45 - toe * 0.03],
+                            [0.025, 0.04 - toe * 0.003],
+                        ));
+                    }
+                    foot
+                })
+                .collect(),
+            BodyView::Scalp => vec![
+                ellipse([0.0, 0.0], [0.55, 0.7]),
+                vec![[-0.08, 0.69], [0.0, 0.8], [0.08, 0.69]],
+                ellipse([-0.58, 0.05], [0.06, 0.14]),
+                ellipse([0.58, 0.05], [0.06, 0.14]),
+            ],
+        }
+    }
+}
+
+/// Where a mole was placed on the body map.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BodyMarker {
+    pub view: BodyView,
+    pub position: Point,
+}
+
+/// The mole whose marker on `view` is closest to `point`, if close enough to be clicked.
+pub fn marker_at(moles: &[Mole], view: BodyView, point: Point) -> Option<MoleId> {
+    moles
+        .iter()
+        .filter_map(|mole| {
+            let marker = mole.marker.filter(|marker| marker.view == view)?;
+            let distance = (marker.position[0] - point[0]).hypot(marker.position[1] - point[1]);
+            Some((mole.id, distance)).filter(|(_, distance)| *distance <= MARKER_RADIUS)
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(id, _)| id)
+}
+
+fn ellipse(center: Point, radii: [f32; 2]) -> Vec<Point> {
+    (0..ELLIPSE_SAMPLES)
+        .map(|index| {
+            let angle = 2.0 * PI * index as f32 / ELLIPSE_SAMPLES as f32;
+            [
+                center[0] + radii[0] * angle.cos(),
+                center[1] + radii[1] * angle.sin(),
+            ]
+        })
+        .collect()
+}
+
+#[test]
+fn test_marker_at() {
+    let mole = |id, marker| Mole {
+        id,
+        label: String::new(),
+        location: String::new(),
+        marker,
+    };
+    let moles = [
+        mole(1, None),
+        mole(
+            2,
+            Some(BodyMarker {
+                view: BodyView::Back,
+                position: [0.1, 0.3],
+            }),
+        ),
+        mole(
+            3,
+            Some(BodyMarker {
+                view: BodyView::Back,
+                position: [0.14, 0.3],
+            }),
+        ),
+        mole(
+            4,
+            Some(BodyMarker {
+                view: BodyView::Front,
+                position: [0.1, 0.3],
+            }),
+        ),
+    ];
+    assert_eq!(marker_at(&moles, BodyView::Back, [0.11, 0.31]), Some(2));
+    assert_eq!(marker_at(&moles, BodyView::Back, [0.13, 0.3]), Some(3));
+    assert_eq!(marker_at(&moles, BodyView::Front, [0.1, 0.32]), Some(4));
+    assert_eq!(marker_at(&moles, BodyView::Front, [0.5, 0.3]), None);
+    assert_eq!(marker_at(&moles, BodyView::Scalp, [0.1, 0.3]), None);
+
+    for view in BodyView::ALL {
+        for point in view.silhouette().iter().flatten() {
+            assert!(point[0].abs() <= 1.0 && point[1].abs() <= 1.0, "{:?}", view);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use image::DynamicImage;
+use std::time::{Duration, SystemTime};
+
+/// Largest number of differing bits between the hashes of two shots of the same spot.
+const HASH_DISTANCE_MAX: u32 = 10;
+/// Longest interval between two shots of a burst.
+const BURST_INTERVAL_MAX: Duration = Duration::from_secs(10);
+
+/// Difference hash of a photo: one bit per pair of horizontally adjacent cells of a 9x8
+/// grayscale thumbnail, set when the left cell is brighter. Near-identical photos have
+/// hashes differing by a few bits.
+pub fn difference_hash(image: &DynamicImage) -> u64 {
+    let small = image
+        .resize_exact(9, 8, image::imageops::FilterType::Triangle)
+        .to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// What burst detection needs to know of a photo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BurstShot {
+    pub hash: u64,
+    pub time: SystemTime,
+    pub sharpness: f32,
+}
+
+/// Consecutive photos of the same spot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Burst {
+    /// Indices of the photos, in order.
+    pub photos: Vec<usize>,
+    /// Index of the sharpest photo.
+    pub representative: usize,
+}
+
+/// Groups consecutive shots taken close in time whose content is near-identical; shots
+/// alone form bursts of one.
+pub fn group_bursts(shots: &[BurstShot]) -> Vec<Burst> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (index, shot) in shots.iter().enumerate() {
+        let same_burst = index > 0 && {
+            let previous = &shots[index - 1];
+            let interval = match shot.time.duration_since(previous.time) {
+                Ok(interval) => interval,
+                Err(error) => error.duration(),
+            };
+            interval <= BURST_INTERVAL_MAX
+                && (shot.hash ^ previous.hash).count_ones() <= HASH_DISTANCE_MAX
+        };
+        match groups.last_mut() {
+            Some(group) if same_burst => group.push(index),
+            _ => groups.push(vec![index]),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|photos| {
+            let representative = *photos
+                .iter()
+                .max_by(|a, b| shots[**a].sharpness.total_cmp(&shots[**b].sharpness))
+                .unwrap();
+            Burst {
+                photos,
+                representative,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_difference_hash() {
+    let gradient = |offset: u32| {
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(90, 80, |x, y| {
+            let value = ((x + offset) * 2 + (y * 3) % 40) as u8;
+            image::Rgb([value, value, value])
+        }))
+    };
+    let mirrored =
+        DynamicImage::ImageRgb8(image::imageops::flip_horizontal(&gradient(0).to_rgb8()));
+    let hash = difference_hash(&gradient(0));
+    assert!((hash ^ difference_hash(&gradient(1))).count_ones() <= HASH_DISTANCE_MAX);
+    assert!((hash ^ difference_hash(&mirrored)).count_ones() > HASH_DISTANCE_MAX);
+}
+
+#[test]
+fn test_group_bursts() {
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let shot = |hash: u64, seconds: u64, sharpness: f32| BurstShot {
+        hash,
+        time: start + Duration::from_secs(seconds),
+        sharpness,
+    };
+    let bursts = group_bursts(&[
+        shot(0b1111, 0, 10.0),
+        shot(0b0111, 1, 50.0),
+        shot(0b0011, 2, 20.0),
+        // Same spot, but a minute later.
+        shot(0b0011, 62, 20.0),
+        // Another spot.
+        shot(u64::MAX, 63, 20.0),
+    ]);
+    assert_eq!(
+        bursts,
+        vec![
+            Burst {
+                photos: vec![0, 1, 2],
+                representative: 1
+            },
+            Burst {
+                photos: vec![3],
+                representative: 3
+            },
+            Burst {
+                photos: vec![4],
+                representative: 4
+            },
+        ]
+    );
+}
@@ -0,0 +1,494 @@
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::{
+    correction::FlatField,
+    errors::{DScopeError, DScopeResult},
+    imaging::{otsu_threshold, Mask},
+    outline::Point,
+};
+
+const LIBRARY_DIRECTORY_NAME: &str = "d-scope";
+const LIBRARY_FILE_NAME: &str = "calibration.json";
+
+/// Scale of the adapter in `3d-print/base.scad`, used by photos older than profiles.
+pub const LEGACY_PX_PER_MM: f32 = 1250.0;
+pub const LEGACY_PROFILE_NAME: &str = "D-Scope adapter";
+
+pub const DEFAULT_TARGET_SPACING: f32 = 1.0;
+
+const UNDISTORT_ITERATIONS: usize = 10;
+const FIT_ITERATIONS: usize = 100;
+const DETECTION_WIDTH: u32 = 1024;
+const MIN_DOT_AREA: usize = 12;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationProfile {
+    pub name: String,
+    pub px_per_mm: f32,
+    #[serde(default)]
+    pub center: [f32; 2],
+    #[serde(default)]
+    pub k1: f32,
+    #[serde(default)]
+    pub k2: f32,
+    #[serde(default)]
+    pub flat_field: Option<FlatField>,
+}
+
+impl Default for CalibrationProfile {
+    fn default() -> Self {
+        Self {
+            name: LEGACY_PROFILE_NAME.to_string(),
+            px_per_mm: LEGACY_PX_PER_MM,
+            center: [0.0, 0.0],
+            k1: 0.0,
+            k2: 0.0,
+            flat_field: None,
+        }
+    }
+}
+
+impl CalibrationProfile {
+    /// Returns pixels from the center of the photo, y up.
+    pub fn distort(&self, point: Point) -> [f32; 2] {
+        let r2 = point[0] * point[0] + point[1] * point[1];
+        let factor = self.px_per_mm * (1.0 + self.k1 * r2 + self.k2 * r2 * r2);
+        [
+            self.center[0] + point[0] * factor,
+            self.center[1] + point[1] * factor,
+        ]
+    }
+
+    pub fn undistort(&self, pixel: [f32; 2]) -> Point {
+        let distorted = [
+            (pixel[0] - self.center[0]) / self.px_per_mm,
+            (pixel[1] - self.center[1]) / self.px_per_mm,
+        ];
+        let mut point = distorted;
+        for _ in 0..UNDISTORT_ITERATIONS {
+            let r2 = point[0] * point[0] + point[1] * point[1];
+            let factor = 1.0 + self.k1 * r2 + self.k2 * r2 * r2;
+            point = [distorted[0] / factor, distorted[1] / factor];
+        }
+        point
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationLibrary {
+    pub profiles: Vec<CalibrationProfile>,
+}
+
+impl Default for CalibrationLibrary {
+    fn default() -> Self {
+        Self {
+            profiles: vec![CalibrationProfile::default()],
+        }
+    }
+}
+
+impl CalibrationLibrary {
+    pub fn load() -> DScopeResult<Self> {
+        let path = match library_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Default::default()),
+        };
+        let text = std::fs::read_to_string(&path).map_err(|error| {
+            DScopeError::cannot_read_file(error, path.to_string_lossy().to_string())
+        })?;
+        let mut library: Self = serde_json::from_str(&text).map_err(|error| {
+            DScopeError::cannot_decode_info(error, path.to_string_lossy().to_string())
+        })?;
+        if library.profile(LEGACY_PROFILE_NAME).is_none() {
+            library.profiles.insert(0, CalibrationProfile::default());
+        }
+        Ok(library)
+    }
+
+    pub fn save(&self) -> DScopeResult<()> {
+        let path = match library_path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory).map_err(|error| {
+                DScopeError::cannot_create_directory(error, directory.to_string_lossy().to_string())
+            })?;
+        }
+        let text = serde_json::to_string_pretty(self).unwrap();
+        std::fs::write(&path, text).map_err(|error| {
+            DScopeError::cannot_write_file(error, path.to_string_lossy().to_string())
+        })
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&CalibrationProfile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    pub fn add(&mut self, profile: CalibrationProfile) {
+        match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+}
+
+fn library_path() -> Option<PathBuf> {
+    let directory = std::env::var_os("APPDATA").or_else(|| std::env::var_os("XDG_CONFIG_HOME"));
+    let mut path = match directory {
+        Some(directory) => PathBuf::from(directory),
+        None => {
+            let mut home = PathBuf::from(std::env::var_os("HOME")?);
+            home.push(".config");
+            home
+        }
+    };
+    path.push(LIBRARY_DIRECTORY_NAME);
+    path.push(LIBRARY_FILE_NAME);
+    Some(path)
+}
+
+#[test]
+fn test_calibration_library_add() {
+    let mut library = CalibrationLibrary::default();
+    library.add(CalibrationProfile {
+        name: "Phone 2".to_string(),
+        px_per_mm: 900.0,
+        ..Default::default()
+    });
+    library.add(CalibrationProfile {
+        name: "Phone 2".to_string(),
+        px_per_mm: 950.0,
+        ..Default::default()
+    });
+    assert_eq!(library.profiles.len(), 2);
+    assert_eq!(library.profile("Phone 2").unwrap().px_per_mm, 950.0);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalibrationTarget {
+    Ruler,
+    DotGrid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationFit {
+    pub profile: CalibrationProfile,
+    pub predicted: Vec<[f32; 2]>,
+    pub residual: f32,
+}
+
+/// `marks` are in pixels from the center of the photo, y up.
+pub fn fit_calibration(
+    marks: &[[f32; 2]],
+    target: CalibrationTarget,
+    spacing: f32,
+) -> Option<CalibrationFit> {
+    let positions: Vec<Point> = target_positions(marks, target)?
+        .iter()
+        .map(|position| [position[0] * spacing, position[1] * spacing])
+        .collect();
+
+    let mut profile = CalibrationProfile {
+        name: String::new(),
+        ..Default::default()
+    };
+    let mut transform = Similarity::default();
+    for iteration in 0..FIT_ITERATIONS {
+        let undistorted: Vec<[f32; 2]> = marks
+            .iter()
+            .map(|mark| {
+                let point = profile.undistort(*mark);
+                [point[0] * profile.px_per_mm, point[1] * profile.px_per_mm]
+            })
+            .collect();
+        transform = Similarity::fit(&positions, &undistorted)?;
+        profile.px_per_mm = transform.scale();
+        if iteration == 0 && marks.len() < 3 {
+            break;
+        }
+
+        let (mut numerator, mut denominator) = (0.0, 0.0);
+        for (position, mark) in positions.iter().zip(marks.iter()) {
+            let ideal = transform.apply(*position);
+            let r2 = (ideal[0] * ideal[0] + ideal[1] * ideal[1])
+                / (profile.px_per_mm * profile.px_per_mm);
+            let error = [mark[0] - ideal[0], mark[1] - ideal[1]];
+            numerator += r2 * (error[0] * ideal[0] + error[1] * ideal[1]);
+            denominator += r2 * r2 * (ideal[0] * ideal[0] + ideal[1] * ideal[1]);
+        }
+        profile.k1 = if denominator > 0.0 {
+            numerator / denominator
+        } else {
+            0.0
+        };
+    }
+
+    let predicted: Vec<[f32; 2]> = positions
+        .iter()
+        .map(|position| {
+            let ideal = transform.apply(*position);
+            profile.distort([ideal[0] / profile.px_per_mm, ideal[1] / profile.px_per_mm])
+        })
+        .collect();
+    let residual = (predicted
+        .iter()
+        .zip(marks.iter())
+        .map(|(p, m)| (p[0] - m[0]).powi(2) + (p[1] - m[1]).powi(2))
+        .sum::<f32>()
+        / marks.len() as f32)
+        .sqrt();
+    Some(CalibrationFit {
+        profile,
+        predicted,
+        residual,
+    })
+}
+
+fn target_positions(marks: &[[f32; 2]], target: CalibrationTarget) -> Option<Vec<Point>> {
+    if marks.len() < 2 {
+        return None;
+    }
+    match target {
+        CalibrationTarget::Ruler => {
+            // Project the ticks on the direction of the ruler, given by their principal axis.
+            let n = marks.len() as f32;
+            let mean = [
+                marks.iter().map(|m| m[0]).sum::<f32>() / n,
+                marks.iter().map(|m| m[1]).sum::<f32>() / n,
+            ];
+            let (mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0);
+            for mark in marks.iter() {
+                let (dx, dy) = (mark[0] - mean[0], mark[1] - mean[1]);
+                sxx += dx * dx;
+                syy += dy * dy;
+                sxy += dx * dy;
+            }
+            let angle = 0.5 * (2.0 * sxy).atan2(sxx - syy);
+            let along: Vec<f32> = marks
+                .iter()
+                .map(|m| (m[0] - mean[0]) * angle.cos() + (m[1] - mean[1]) * angle.sin())
+                .collect();
+            let mut sorted = along.clone();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let mut gaps: Vec<f32> = sorted
+                .windows(2)
+                .map(|pair| pair[1] - pair[0])
+                .filter(|gap| *gap > 0.0)
+                .collect();
+            gaps.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let gap = *gaps.get(gaps.len() / 2)?;
+            Some(
+                along
+                    .iter()
+                    .map(|t| [((t - sorted[0]) / gap).round(), 0.0])
+                    .collect(),
+            )
+        }
+        CalibrationTarget::DotGrid => {
+            // Count rows and columns from the least distorted dot, along its nearest neighbour.
+            let length = |p: &[f32; 2]| p[0] * p[0] + p[1] * p[1];
+            let origin = *marks
+                .iter()
+                .min_by(|a, b| length(a).partial_cmp(&length(b)).unwrap())?;
+            let step = marks
+                .iter()
+                .map(|m| [m[0] - origin[0], m[1] - origin[1]])
+                .filter(|d| length(d) > 0.0)
+                .min_by(|a, b| length(a).partial_cmp(&length(b)).unwrap())?;
+            let step_length = length(&step);
+            Some(
+                marks
+                    .iter()
+                    .map(|m| {
+                        let d = [m[0] - origin[0], m[1] - origin[1]];
+                        [
+                            ((d[0] * step[0] + d[1] * step[1]) / step_length).round(),
+                            ((-d[0] * step[1] + d[1] * step[0]) / step_length).round(),
+                        ]
+                    })
+                    .collect(),
+            )
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Similarity {
+    a: f32,
+    b: f32,
+    t: [f32; 2],
+}
+
+impl Similarity {
+    fn fit(from: &[Point], to: &[[f32; 2]]) -> Option<Self> {
+        let n = from.len() as f32;
+        let mean = |points: &[[f32; 2]]| {
+            [
+                points.iter().map(|p| p[0]).sum::<f32>() / n,
+                points.iter().map(|p| p[1]).sum::<f32>() / n,
+            ]
+        };
+        let (from_mean, to_mean) = (mean(from), mean(to));
+        let (mut a, mut b, mut norm) = (0.0, 0.0, 0.0);
+        for (f, t) in from.iter().zip(to.iter()) {
+            let f = [f[0] - from_mean[0], f[1] - from_mean[1]];
+            let t = [t[0] - to_mean[0], t[1] - to_mean[1]];
+            a += f[0] * t[0] + f[1] * t[1];
+            b += f[0] * t[1] - f[1] * t[0];
+            norm += f[0] * f[0] + f[1] * f[1];
+        }
+        if norm <= 0.0 {
+            return None;
+        }
+        let (a, b) = (a / norm, b / norm);
+        Some(Self {
+            a,
+            b,
+            t: [
+                to_mean[0] - (a * from_mean[0] - b * from_mean[1]),
+                to_mean[1] - (b * from_mean[0] + a * from_mean[1]),
+            ],
+        })
+    }
+
+    fn scale(&self) -> f32 {
+        (self.a * self.a + self.b * self.b).sqrt()
+    }
+
+    fn apply(&self, point: Point) -> [f32; 2] {
+        [
+            self.a * point[0] - self.b * point[1] + self.t[0],
+            self.b * point[0] + self.a * point[1] + self.t[1],
+        ]
+    }
+}
+
+/// Returns the centers in pixels from the center of the photo, y up.
+pub fn detect_dots(image: &DynamicImage) -> Vec<[f32; 2]> {
+    let width = DETECTION_WIDTH.min(image.width());
+    let height = ((image.height() as f32) * (width as f32) / (image.width() as f32)) as u32;
+    let small = image.thumbnail_exact(width, height.max(1)).to_luma8();
+    let scale = image.width() as f32 / small.width() as f32;
+
+    let threshold = otsu_threshold(small.pixels().map(|pixel| pixel[0]));
+    let dark = Mask::from_fn(small.width(), small.height(), |x, y| {
+        small.get_pixel(x, y)[0] <= threshold
+    })
+    .open(1);
+    let edge = Mask::from_fn(small.width(), small.height(), |x, y| {
+        x == 0 || y == 0 || x + 1 == small.width() || y + 1 == small.height()
+    });
+    let components: Vec<_> = dark
+        .components()
+        .into_iter()
+        .filter(|component| component.area() >= MIN_DOT_AREA && !component.touches(&edge))
+        .collect();
+
+    let mut areas: Vec<usize> = components.iter().map(|c| c.area()).collect();
+    areas.sort_unstable();
+    let typical_area = match areas.get(areas.len() / 2) {
+        Some(area) => *area,
+        None => return Vec::new(),
+    };
+    components
+        .iter()
+        .filter(|c| c.area() * 2 >= typical_area && c.area() <= typical_area * 2)
+        .map(|component| {
+            let n = component.area() as f32;
+            let x = component
+                .pixels
+                .iter()
+                .map(|p| p.0 as f32 + 0.5)
+                .sum::<f32>()
+                / n;
+            let y = component
+                .pixels
+                .iter()
+                .map(|p| p.1 as f32 + 0.5)
+                .sum::<f32>()
+                / n;
+            [
+                (x - small.width() as f32 / 2.0) * scale,
+                (small.height() as f32 / 2.0 - y) * scale,
+            ]
+        })
+        .collect()
+}
+
+#[test]
+fn test_undistort() {
+    let profile = CalibrationProfile {
+        px_per_mm: 800.0,
+        center: [10.0, -5.0],
+        k1: -0.002,
+        ..Default::default()
+    };
+    let point = profile.undistort(profile.distort([3.0, -4.0]));
+    assert!((point[0] - 3.0).abs() < 1e-3 && (point[1] + 4.0).abs() < 1e-3);
+}
+
+#[test]
+fn test_fit_dot_grid() {
+    let profile = CalibrationProfile {
+        px_per_mm: 800.0,
+        k1: -0.001,
+        ..Default::default()
+    };
+    let positions: Vec<Point> = (-4..=4)
+        .flat_map(|i| (-3..=3).map(move |j| [i as f32, j as f32]))
+        .collect();
+    // The target slightly rotated and off center.
+    let (sin, cos) = 0.1f32.sin_cos();
+    let marks: Vec<[f32; 2]> = positions
+        .iter()
+        .map(|p| profile.distort([p[0] * cos - p[1] * sin + 0.3, p[0] * sin + p[1] * cos - 0.2]))
+        .collect();
+    let fit = fit_calibration(&marks, CalibrationTarget::DotGrid, 1.0).unwrap();
+    assert!(
+        (fit.profile.px_per_mm - 800.0).abs() < 1.0,
+        "{:?}",
+        fit.profile
+    );
+    assert!((fit.profile.k1 + 0.001).abs() < 1e-4, "{:?}", fit.profile);
+    assert!(fit.residual < 0.5, "{}", fit.residual);
+}
+
+#[test]
+fn test_fit_ruler() {
+    let profile = CalibrationProfile {
+        px_per_mm: 1200.0,
+        ..Default::default()
+    };
+    // Every other tick of a half millimetre ruler, with one missed.
+    let positions: Vec<Point> = [-3, -2, -1, 0, 2, 3]
+        .iter()
+        .map(|i| [*i as f32 * 0.5, 0.0])
+        .collect();
+    // The target slightly rotated and off center.
+    let (sin, cos) = 0.1f32.sin_cos();
+    let marks: Vec<[f32; 2]> = positions
+        .iter()
+        .map(|p| profile.distort([p[0] * cos - p[1] * sin + 0.3, p[0] * sin + p[1] * cos - 0.2]))
+        .collect();
+    let fit = fit_calibration(&marks, CalibrationTarget::Ruler, 0.5).unwrap();
+    assert!(
+        (fit.profile.px_per_mm - 1200.0).abs() < 1.0,
+        "{:?}",
+        fit.profile
+    );
+}
+
+#[test]
+fn test_detect_dots() {
+    let image = image::GrayImage::from_fn(200, 100, |x, y| {
+        let near_dot = (x % 40).abs_diff(20) <= 4 && (y % 40).abs_diff(20) <= 4;
+        image::Luma([if near_dot { 20 } else { 230 }])
+    });
+    let mut dots = detect_dots(&DynamicImage::ImageLuma8(image));
+    dots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(dots.len(), 10);
+    assert_eq!(dots[0], [-79.5, -10.5]);
+}
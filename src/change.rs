@@ -0,0 +1,167 @@
+use image::{RgbImage, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+use crate::{glare::glare_mask, photo_set::MoleMetrics, segmentation::metrics_mask};
+
+/// Distance around the lesions where colour changes are looked for, in millimetres.
+const CHANGE_MARGIN: f32 = 1.0;
+/// Colour difference, in sRGB units, below which pixels are considered unchanged.
+const COLOUR_NOISE: f32 = 12.0;
+/// Colour difference shown at full strength on the heatmap.
+const COLOUR_FULL_SCALE: f32 = 80.0;
+/// Overlay colours of the area gained and lost by the lesion.
+const GAINED_COLOUR: Rgba<u8> = Rgba([255, 40, 40, 170]);
+const LOST_COLOUR: Rgba<u8> = Rgba([40, 120, 255, 170]);
+
+/// Differences between two registered photos of a mole, outline and colour.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ChangeMeasures {
+    /// Relative change of the area of the lesion, in percent.
+    pub area_change: f32,
+    /// Area inside the new outline only, and inside the old one only, in mm².
+    pub gained_area: f32,
+    pub lost_area: f32,
+    /// Mean colour difference where the lesion was and still is, in sRGB units.
+    pub colour_shift: f32,
+}
+
+/// Changes of a photo since the photo it was compared with.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ChangeSummary {
+    pub baseline_time: SystemTime,
+    pub baseline_photo_id: usize,
+    pub measures: ChangeMeasures,
+}
+
+impl std::fmt::Display for ChangeMeasures {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "area {:+.1}% (+{:.2} -{:.2} mm²), colour shift {:.1}",
+            self.area_change, self.gained_area, self.lost_area, self.colour_shift
+        ))
+    }
+}
+
+/// Compares a photo with a baseline already resampled into its coordinates, both of
+/// scale `px_per_mm`, with the measures of the mole in each.
+///
+/// Returns a heatmap of the same size as the photo, showing the colour difference around
+/// the lesions, and the area gained and lost over it, with the summary of the changes.
+/// Glare is left out of the colour difference. Returns `None` when either photo has not
+/// been measured.
+pub fn detect_changes(
+    photo: &RgbImage,
+    metrics: &MoleMetrics,
+    baseline: &RgbImage,
+    baseline_metrics: &MoleMetrics,
+    px_per_mm: f32,
+) -> Option<(RgbaImage, ChangeMeasures)> {
+    let area = metrics.area().or_else(|| metrics.size().map(circle_area))?;
+    let baseline_area = baseline_metrics
+        .area()
+        .or_else(|| baseline_metrics.size().map(circle_area))?;
+
+    let (width, height) = photo.dimensions();
+    let lesion = metrics_mask(metrics, width, height, px_per_mm);
+    let baseline_lesion = metrics_mask(baseline_metrics, width, height, px_per_mm);
+    let region = lesion
+        .dilate((CHANGE_MARGIN * px_per_mm) as u32)
+        .union(&baseline_lesion.dilate((CHANGE_MARGIN * px_per_mm) as u32));
+    let glare = glare_mask(photo).union(&glare_mask(baseline));
+
+    let mut overlay = RgbaImage::new(width, height);
+    let (mut gained, mut lost) = (0usize, 0usize);
+    let (mut shift_sum, mut shift_count) = (0.0, 0usize);
+    for y in 0..height {
+        for x in 0..width {
+            let (inside, was_inside) = (lesion.get(x, y), baseline_lesion.get(x, y));
+            if inside && !was_inside {
+                gained += 1;
+                overlay.put_pixel(x, y, GAINED_COLOUR);
+                continue;
+            }
+            if was_inside && !inside {
+                lost += 1;
+                overlay.put_pixel(x, y, LOST_COLOUR);
+                continue;
+            }
+            if !region.get(x, y) || glare.get(x, y) {
+                continue;
+            }
+            let (a, b) = (photo.get_pixel(x, y), baseline.get_pixel(x, y));
+            let difference = ((a[0] as f32 - b[0] as f32).powi(2)
+                + (a[1] as f32 - b[1] as f32).powi(2)
+                + (a[2] as f32 - b[2] as f32).powi(2))
+            .sqrt();
+            if inside {
+                shift_sum += difference;
+                shift_count += 1;
+            }
+            if difference > COLOUR_NOISE {
+                overlay.put_pixel(x, y, heat_colour(difference));
+            }
+        }
+    }
+
+    let pixel_area = 1.0 / (px_per_mm * px_per_mm);
+    Some((
+        overlay,
+        ChangeMeasures {
+            area_change: (area - baseline_area) / baseline_area * 100.0,
+            gained_area: gained as f32 * pixel_area,
+            lost_area: lost as f32 * pixel_area,
+            colour_shift: if shift_count > 0 {
+                shift_sum / shift_count as f32
+            } else {
+                0.0
+            },
+        },
+    ))
+}
+
+fn circle_area(diameter: f32) -> f32 {
+    std::f32::consts::PI * diameter * diameter / 4.0
+}
+
+/// Yellow to red, more opaque as the difference grows.
+fn heat_colour(difference: f32) -> Rgba<u8> {
+    let strength =
+        ((difference - COLOUR_NOISE) / (COLOUR_FULL_SCALE - COLOUR_NOISE)).clamp(0.0, 1.0);
+    Rgba([
+        255,
+        (255.0 * (1.0 - strength)) as u8,
+        0,
+        (60.0 + 150.0 * strength) as u8,
+    ])
+}
+
+#[test]
+fn test_detect_changes() {
+    let skin = image::Rgb([220, 180, 160]);
+    let circle = |diameter: f32| MoleMetrics {
+        diameter,
+        ..Default::default()
+    };
+    // The lesion grew from 2 to 3 mm and darkened.
+    let draw = |diameter: f32, colour: image::Rgb<u8>| {
+        RgbImage::from_fn(64, 64, |x, y| {
+            let d = ((x as f32 + 0.5 - 32.0).powi(2) + (y as f32 + 0.5 - 32.0).powi(2)).sqrt();
+            if d <= diameter * 5.0 {
+                colour
+            } else {
+                skin
+            }
+        })
+    };
+    let baseline = draw(2.0, image::Rgb([120, 80, 60]));
+    let photo = draw(3.0, image::Rgb([90, 60, 40]));
+    let (overlay, measures) =
+        detect_changes(&photo, &circle(3.0), &baseline, &circle(2.0), 10.0).unwrap();
+    assert!((measures.area_change - 125.0).abs() < 0.1, "{:?}", measures);
+    assert!((measures.gained_area - circle_area(3.0) + circle_area(2.0)).abs() < 0.3);
+    assert_eq!(measures.lost_area, 0.0);
+    assert!(measures.colour_shift > 30.0, "{:?}", measures);
+    assert_eq!(*overlay.get_pixel(32 + 12, 32), GAINED_COLOUR);
+    assert_eq!(overlay.get_pixel(0, 0)[3], 0);
+}
@@ -0,0 +1,333 @@
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+
+/// Size of the table encoding linear values back to sRGB.
+const ENCODE_TABLE_SIZE: usize = 4096;
+/// Radius of the area averaged when sampling a reference patch, in pixels.
+pub const PATCH_RADIUS: i64 = 6;
+
+/// sRGB values of the patches of a ColorChecker-like card, row by row.
+pub const COLOUR_CHECKER: [(&str, [u8; 3]); 24] = [
+    ("Dark skin", [115, 82, 68]),
+    ("Light skin", [194, 150, 130]),
+    ("Blue sky", [98, 122, 157]),
+    ("Foliage", [87, 108, 67]),
+    ("Blue flower", [133, 128, 177]),
+    ("Bluish green", [103, 189, 170]),
+    ("Orange", [214, 126, 44]),
+    ("Purplish blue", [80, 91, 166]),
+    ("Moderate red", [193, 90, 99]),
+    ("Purple", [94, 60, 108]),
+    ("Yellow green", [157, 188, 64]),
+    ("Orange yellow", [224, 163, 46]),
+    ("Blue", [56, 61, 150]),
+    ("Green", [70, 148, 73]),
+    ("Red", [175, 54, 60]),
+    ("Yellow", [231, 199, 31]),
+    ("Magenta", [187, 86, 149]),
+    ("Cyan", [8, 133, 161]),
+    ("White", [243, 243, 242]),
+    ("Neutral 8", [200, 200, 200]),
+    ("Neutral 6.5", [160, 160, 160]),
+    ("Neutral 5", [122, 122, 121]),
+    ("Neutral 3.5", [85, 85, 85]),
+    ("Black", [52, 52, 52]),
+];
+
+/// What a sampled patch should look like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColourReference {
+    /// A grey card: any neutral grey as bright as the sample.
+    Neutral,
+    /// A patch of `COLOUR_CHECKER`.
+    Patch(usize),
+}
+
+impl ColourReference {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColourReference::Neutral => "Grey card",
+            ColourReference::Patch(index) => COLOUR_CHECKER[*index].0,
+        }
+    }
+
+    /// Target linear colour of a patch measured as `measured`, linear too.
+    fn target(&self, measured: [f32; 3]) -> [f32; 3] {
+        match self {
+            ColourReference::Neutral => {
+                let grey = (measured[0] + measured[1] + measured[2]) / 3.0;
+                [grey, grey, grey]
+            }
+            ColourReference::Patch(index) => {
+                let srgb = COLOUR_CHECKER[*index].1;
+                [
+                    srgb_to_linear(srgb[0]),
+                    srgb_to_linear(srgb[1]),
+                    srgb_to_linear(srgb[2]),
+                ]
+            }
+        }
+    }
+}
+
+/// A patch of a reference card clicked on a photo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColourSample {
+    /// Where the patch was clicked, in pixels.
+    pub x: u32,
+    pub y: u32,
+    /// Average sRGB colour around that point.
+    pub measured: [f32; 3],
+    pub reference: ColourReference,
+}
+
+impl ColourSample {
+    pub fn new(image: &RgbImage, x: u32, y: u32, reference: ColourReference) -> Self {
+        let mut sum = [0.0; 3];
+        let mut count = 0.0;
+        for dy in -PATCH_RADIUS..=PATCH_RADIUS {
+            for dx in -PATCH_RADIUS..=PATCH_RADIUS {
+                let (sx, sy) = (x as i64 + dx, y as i64 + dy);
+                if sx < 0 || sy < 0 || sx >= image.width() as i64 || sy >= image.height() as i64 {
+                    continue;
+                }
+                let pixel = image.get_pixel(sx as u32, sy as u32);
+                for channel in 0..3 {
+                    sum[channel] += pixel[channel] as f32;
+                }
+                count += 1.0;
+            }
+        }
+        Self {
+            x,
+            y,
+            measured: [sum[0] / count, sum[1] / count, sum[2] / count],
+            reference,
+        }
+    }
+}
+
+/// Colour correction of a visit, applied to linear RGB.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColourMatrix {
+    pub matrix: [[f32; 3]; 3],
+}
+
+impl Default for ColourMatrix {
+    fn default() -> Self {
+        Self {
+            matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+}
+
+impl ColourMatrix {
+    /// Least squares fit of the matrix taking the samples to their references.
+    ///
+    /// With fewer than three samples, or samples that are all alike, as with a grey card,
+    /// only the white balance can be corrected and the matrix is diagonal.
+    pub fn fit(samples: &[ColourSample]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let pairs: Vec<([f32; 3], [f32; 3])> = samples
+            .iter()
+            .map(|sample| {
+                let measured = [
+                    srgb_to_linear_f32(sample.measured[0]),
+                    srgb_to_linear_f32(sample.measured[1]),
+                    srgb_to_linear_f32(sample.measured[2]),
+                ];
+                (measured, sample.reference.target(measured))
+            })
+            .collect();
+
+        let mut normal = [[0.0f32; 3]; 3];
+        let mut right = [[0.0f32; 3]; 3];
+        for (measured, target) in pairs.iter() {
+            for i in 0..3 {
+                for j in 0..3 {
+                    normal[i][j] += measured[i] * measured[j];
+                    right[i][j] += measured[i] * target[j];
+                }
+            }
+        }
+
+        let inverse = if pairs.len() >= 3 {
+            invert(&normal)
+        } else {
+            None
+        };
+        let matrix = match inverse {
+            Some(inverse) => {
+                // Row r of the matrix solves normal * row = right[.][r].
+                let mut matrix = [[0.0; 3]; 3];
+                for (r, row) in matrix.iter_mut().enumerate() {
+                    for (c, value) in row.iter_mut().enumerate() {
+                        *value = (0..3).map(|k| inverse[c][k] * right[k][r]).sum();
+                    }
+                }
+                matrix
+            }
+            None => {
+                let mut matrix = [[0.0; 3]; 3];
+                for (channel, row) in matrix.iter_mut().enumerate() {
+                    if normal[channel][channel] <= 0.0 {
+                        return None;
+                    }
+                    row[channel] = right[channel][channel] / normal[channel][channel];
+                }
+                matrix
+            }
+        };
+        Some(Self { matrix })
+    }
+
+    /// Corrects an sRGB colour.
+    pub fn apply_srgb(&self, colour: [f32; 3]) -> [f32; 3] {
+        let linear = [
+            srgb_to_linear_f32(colour[0]),
+            srgb_to_linear_f32(colour[1]),
+            srgb_to_linear_f32(colour[2]),
+        ];
+        let corrected = self.apply_linear(linear);
+        [
+            linear_to_srgb(corrected[0]),
+            linear_to_srgb(corrected[1]),
+            linear_to_srgb(corrected[2]),
+        ]
+    }
+
+    fn apply_linear(&self, colour: [f32; 3]) -> [f32; 3] {
+        let mut result = [0.0; 3];
+        for (row, value) in self.matrix.iter().zip(result.iter_mut()) {
+            *value = row[0] * colour[0] + row[1] * colour[1] + row[2] * colour[2];
+        }
+        result
+    }
+
+    pub fn apply(&self, image: &mut RgbImage) {
+        let decode: Vec<f32> = (0..=255).map(srgb_to_linear).collect();
+        let encode: Vec<u8> = (0..ENCODE_TABLE_SIZE)
+            .map(|index| {
+                linear_to_srgb(index as f32 / (ENCODE_TABLE_SIZE - 1) as f32).round() as u8
+            })
+            .collect();
+        for pixel in image.pixels_mut() {
+            let linear = [
+                decode[pixel[0] as usize],
+                decode[pixel[1] as usize],
+                decode[pixel[2] as usize],
+            ];
+            let corrected = self.apply_linear(linear);
+            for channel in 0..3 {
+                let index = (corrected[channel].clamp(0.0, 1.0) * (ENCODE_TABLE_SIZE - 1) as f32)
+                    .round() as usize;
+                pixel[channel] = encode[index];
+            }
+        }
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    srgb_to_linear_f32(value as f32)
+}
+
+fn srgb_to_linear_f32(value: f32) -> f32 {
+    let value = value / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a linear value as sRGB in 0 to 255.
+fn linear_to_srgb(value: f32) -> f32 {
+    let value = value.clamp(0.0, 1.0);
+    let encoded = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    encoded * 255.0
+}
+
+fn invert(m: &[[f32; 3]; 3]) -> Option<[[f32; 3]; 3]> {
+    let cofactor =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let determinant = m[0][0] * cofactor(1, 2, 1, 2) - m[0][1] * cofactor(1, 2, 0, 2)
+        + m[0][2] * cofactor(1, 2, 0, 1);
+    let scale: f32 = m.iter().flatten().map(|v| v.abs()).fold(0.0, f32::max);
+    if determinant.abs() <= 1e-6 * scale * scale * scale {
+        return None;
+    }
+    let adjugate = [
+        [
+            cofactor(1, 2, 1, 2),
+            -cofactor(0, 2, 1, 2),
+            cofactor(0, 1, 1, 2),
+        ],
+        [
+            -cofactor(1, 2, 0, 2),
+            cofactor(0, 2, 0, 2),
+            -cofactor(0, 1, 0, 2),
+        ],
+        [
+            cofactor(1, 2, 0, 1),
+            -cofactor(0, 2, 0, 1),
+            cofactor(0, 1, 0, 1),
+        ],
+    ];
+    let mut inverse = [[0.0; 3]; 3];
+    for r in 0..3 {
+        for c in 0..3 {
+            inverse[r][c] = adjugate[r][c] / determinant;
+        }
+    }
+    Some(inverse)
+}
+
+#[test]
+fn test_grey_card_white_balance() {
+    let matrix = ColourMatrix::fit(&[ColourSample {
+        x: 0,
+        y: 0,
+        measured: [150.0, 120.0, 100.0],
+        reference: ColourReference::Neutral,
+    }])
+    .unwrap();
+    let corrected = matrix.apply_srgb([150.0, 120.0, 100.0]);
+    assert!((corrected[0] - corrected[1]).abs() < 0.5, "{:?}", corrected);
+    assert!((corrected[1] - corrected[2]).abs() < 0.5, "{:?}", corrected);
+}
+
+#[test]
+fn test_colour_checker_fit() {
+    // A warm LED cast: red boosted, blue reduced.
+    let cast = ColourMatrix {
+        matrix: [[1.2, 0.05, 0.0], [0.0, 1.0, 0.0], [0.0, 0.1, 0.7]],
+    };
+    let samples: Vec<ColourSample> = (0..COLOUR_CHECKER.len())
+        .map(|index| {
+            let srgb = COLOUR_CHECKER[index].1;
+            let measured = cast.apply_srgb([srgb[0] as f32, srgb[1] as f32, srgb[2] as f32]);
+            ColourSample {
+                x: 0,
+                y: 0,
+                measured,
+                reference: ColourReference::Patch(index),
+            }
+        })
+        .collect();
+    let matrix = ColourMatrix::fit(&samples).unwrap();
+    let corrected = matrix.apply_srgb(samples[6].measured);
+    let expected = COLOUR_CHECKER[6].1;
+    for channel in 0..3 {
+        assert!(
+            (corrected[channel] - expected[channel] as f32).abs() < 3.0,
+            "{:?}",
+            corrected
+        );
+    }
+}
@@ -0,0 +1,321 @@
+use eframe::{egui::plot::LinkedAxisGroup, epaint::ColorImage};
+use egui_extras::RetainedImage;
+use image::{load_from_memory, RgbImage};
+use std::{path::PathBuf, time::SystemTime};
+
+use crate::{
+    calibration::CalibrationProfile,
+    change::{detect_changes, ChangeSummary},
+    colour::ColourMatrix,
+    errors::{DScopeError, DScopeResult},
+    mole::MoleObservation,
+    photo_set::{
+        correct, photo_file_name, retained_image, Derivation, DisplayTime, MoleMetrics, PhotoSet,
+    },
+    registration::{register, warp, Alignment, Registration},
+};
+
+/// Seconds each photo stays visible when flickering between the two.
+pub const FLICKER_PERIOD: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareMode {
+    SideBySide,
+    Overlay,
+}
+
+/// A photo the current one can be compared with.
+#[derive(Debug, Clone)]
+pub struct CompareSource {
+    pub label: String,
+    pub visit_path: PathBuf,
+    pub photo_id: usize,
+    pub time: SystemTime,
+    pub mole_metrics: MoleMetrics,
+    pub calibration: CalibrationProfile,
+    pub colour_matrix: Option<ColourMatrix>,
+    pub registration: Option<Registration>,
+    pub derived_from: Option<Derivation>,
+}
+
+/// Lists the other photos of the visit and the photos of the same mole taken during
+/// other visits, most recent first.
+pub fn compare_sources(
+    photos: &PhotoSet,
+    current_photo_index: usize,
+    history: &[MoleObservation],
+) -> Vec<CompareSource> {
+    let mut sources: Vec<CompareSource> = history
+        .iter()
+        .rev()
+        .filter(|observation| observation.visit_path != photos.path)
+        .map(|observation| CompareSource {
+            label: format!(
+                "{} [{}]",
+                DisplayTime::new(observation.info.time),
+                observation.photo_id
+            ),
+            visit_path: observation.visit_path.clone(),
+            photo_id: observation.photo_id,
+            time: observation.info.time,
+            mole_metrics: observation.info.mole_metrics.clone(),
+            calibration: observation.calibration.clone(),
+            colour_matrix: observation.colour_matrix,
+            registration: observation.info.registration,
+            derived_from: observation.info.derived_from.clone(),
+        })
+        .collect();
+
+    sources.extend(
+        photos
+            .photos
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != current_photo_index)
+            .map(|(_, photo)| CompareSource {
+                label: format!("this visit [{}]", photo.id),
+                visit_path: photos.path.clone(),
+                photo_id: photo.id,
+                time: photo.info.time,
+                mole_metrics: photo.info.mole_metrics.clone(),
+                calibration: photos.info.calibration.clone(),
+                colour_matrix: photos.info.colour_matrix,
+                registration: photo.info.registration,
+                derived_from: photo.info.derived_from.clone(),
+            }),
+    );
+    sources
+}
+
+pub struct Comparison {
+    pub sources: Vec<CompareSource>,
+    pub source_index: usize,
+    /// The compared photo, corrected.
+    pub image: RgbImage,
+    /// The compared photo as displayed, registered to the current one when possible.
+    pub photo: RetainedImage,
+    /// Scale and measures of the displayed photo.
+    pub px_per_mm: f32,
+    pub mole_metrics: MoleMetrics,
+    /// Alignment from the current photo to the compared one, if either was registered
+    /// to the other.
+    alignment: Option<Alignment>,
+    /// Heatmap of the changes of the current photo, in its coordinates.
+    pub changes: Option<RetainedImage>,
+    pub mode: CompareMode,
+    pub opacity: f32,
+    pub flicker: bool,
+    pub link: LinkedAxisGroup,
+}
+
+impl Comparison {
+    pub fn new(
+        photos: &PhotoSet,
+        current_photo_index: usize,
+        sources: Vec<CompareSource>,
+    ) -> DScopeResult<Option<Self>> {
+        let image = match sources.first() {
+            Some(source) => load_source(photos, source)?,
+            None => return Ok(None),
+        };
+        let mut comparison = Self {
+            sources,
+            source_index: 0,
+            photo: retained_image("compared-photo", &image),
+            image,
+            px_per_mm: 0.0,
+            mole_metrics: Default::default(),
+            alignment: None,
+            changes: None,
+            mode: CompareMode::SideBySide,
+            opacity: 0.5,
+            flicker: false,
+            link: LinkedAxisGroup::both(),
+        };
+        comparison.update_display(photos, current_photo_index);
+        Ok(Some(comparison))
+    }
+
+    pub fn source(&self) -> &CompareSource {
+        &self.sources[self.source_index]
+    }
+
+    pub fn select(
+        &mut self,
+        photos: &PhotoSet,
+        current_photo_index: usize,
+        source_index: usize,
+    ) -> DScopeResult<()> {
+        self.image = load_source(photos, &self.sources[source_index])?;
+        self.source_index = source_index;
+        self.update_display(photos, current_photo_index);
+        Ok(())
+    }
+
+    /// Registers the current photo to the compared one and stores the registration with
+    /// the current photo.
+    pub fn register(
+        &mut self,
+        photos: &mut PhotoSet,
+        current_photo_index: usize,
+    ) -> DScopeResult<()> {
+        let photo = &photos.photos[current_photo_index];
+        let image =
+            photo.corrected(&photos.info.calibration, photos.info.colour_matrix.as_ref())?;
+        let source = self.source();
+        let (alignment, correlation) = register(
+            &self.image,
+            source.calibration.px_per_mm,
+            &image,
+            photos.info.calibration.px_per_mm,
+        )
+        .ok_or_else(|| DScopeError::cannot_register_photo(photo_file_name(photo.id)))?;
+        photos.photos[current_photo_index].info.registration = Some(Registration {
+            baseline_time: source.time,
+            baseline_photo_id: source.photo_id,
+            alignment,
+            correlation,
+        });
+        self.update_display(photos, current_photo_index);
+        Ok(())
+    }
+
+    /// Removes the registration of the current photo to the compared one.
+    pub fn unregister(&mut self, photos: &mut PhotoSet, current_photo_index: usize) {
+        photos.photos[current_photo_index].info.registration = None;
+        self.update_display(photos, current_photo_index);
+    }
+
+    /// Whether the photos are shown in registered coordinates.
+    pub fn is_aligned(&self) -> bool {
+        self.alignment.is_some()
+    }
+
+    /// Whether the current photo is registered to the compared one.
+    pub fn is_registered(&self, photos: &PhotoSet, current_photo_index: usize) -> bool {
+        let source = self.source();
+        match photos.photos[current_photo_index].info.registration {
+            Some(registration) => registration.is_to(source.photo_id, source.time),
+            None => false,
+        }
+    }
+
+    /// Maps the changes of the current photo since the compared one, registered to each
+    /// other, and stores their summary with the current photo.
+    pub fn detect_changes(
+        &mut self,
+        photos: &mut PhotoSet,
+        current_photo_index: usize,
+    ) -> DScopeResult<()> {
+        let photo = &photos.photos[current_photo_index];
+        let alignment = self
+            .alignment
+            .ok_or_else(|| DScopeError::cannot_detect_changes(photo_file_name(photo.id)))?;
+        let image =
+            photo.corrected(&photos.info.calibration, photos.info.colour_matrix.as_ref())?;
+        let px_per_mm = photos.info.calibration.px_per_mm;
+        let source = self.source();
+        let baseline = warp(
+            &self.image,
+            source.calibration.px_per_mm,
+            &alignment,
+            px_per_mm,
+            image.dimensions(),
+        );
+        let (overlay, measures) = detect_changes(
+            &image,
+            &photo.info.mole_metrics,
+            &baseline,
+            &self.mole_metrics,
+            px_per_mm,
+        )
+        .ok_or_else(|| DScopeError::cannot_detect_changes(photo_file_name(photo.id)))?;
+        photos.photos[current_photo_index].info.change = Some(ChangeSummary {
+            baseline_time: source.time,
+            baseline_photo_id: source.photo_id,
+            measures,
+        });
+        self.changes = Some(RetainedImage::from_color_image(
+            "change-heatmap",
+            ColorImage::from_rgba_unmultiplied(
+                [overlay.width() as usize, overlay.height() as usize],
+                overlay.as_raw(),
+            ),
+        ));
+        Ok(())
+    }
+
+    /// Whether the compared photo is the visible one at `time` while flickering.
+    pub fn flicker_visible(&self, time: f64) -> bool {
+        ((time / FLICKER_PERIOD) as u64) % 2 == 1
+    }
+
+    /// Shows the compared photo and its measures in the coordinates of the current photo
+    /// when the two are registered, as they are otherwise.
+    fn update_display(&mut self, photos: &PhotoSet, current_photo_index: usize) {
+        let current = &photos.photos[current_photo_index];
+        let source = &self.sources[self.source_index];
+        let registration = current
+            .info
+            .registration
+            .filter(|registration| registration.is_to(source.photo_id, source.time));
+        let alignment = match registration {
+            Some(registration) => Some(registration.alignment),
+            None => source
+                .registration
+                .filter(|registration| registration.is_to(current.id, current.info.time))
+                .map(|registration| registration.alignment.inverse()),
+        };
+
+        self.mole_metrics = source.mole_metrics.clone();
+        match alignment {
+            Some(alignment) => {
+                let px_per_mm = photos.info.calibration.px_per_mm;
+                self.photo = retained_image(
+                    "compared-photo",
+                    &warp(
+                        &self.image,
+                        source.calibration.px_per_mm,
+                        &alignment,
+                        px_per_mm,
+                        self.image.dimensions(),
+                    ),
+                );
+                self.px_per_mm = px_per_mm;
+                self.mole_metrics.align(&alignment.inverse());
+            }
+            None => {
+                self.photo = retained_image("compared-photo", &self.image);
+                self.px_per_mm = source.calibration.px_per_mm;
+            }
+        }
+        self.alignment = alignment;
+        self.changes = None;
+    }
+}
+
+fn load_source(photos: &PhotoSet, source: &CompareSource) -> DScopeResult<RgbImage> {
+    let file_name = photo_file_name(source.photo_id);
+    if source.visit_path == photos.path {
+        return match photos.photo_index(source.photo_id) {
+            Some(index) => {
+                photos.photos[index].corrected(&source.calibration, source.colour_matrix.as_ref())
+            }
+            None => Err(DScopeError::no_photos_found(file_name)),
+        };
+    }
+
+    let mut path = source.visit_path.clone();
+    path.push(&file_name);
+    let bytes = std::fs::read(&path).map_err(|error| {
+        DScopeError::cannot_read_file(error, path.to_string_lossy().to_string())
+    })?;
+    let image = load_from_memory(&bytes)
+        .map_err(|error| DScopeError::cannot_decode_image(error, file_name))?;
+    Ok(correct(
+        &image,
+        source.derived_from.as_ref(),
+        &source.calibration,
+        source.colour_matrix.as_ref(),
+    ))
+}
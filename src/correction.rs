@@ -0,0 +1,189 @@
+use image::{DynamicImage, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use crate::{calibration::CalibrationProfile, colour::ColourMatrix};
+
+/// Width of the gain map of a flat field; vignetting varies slowly across the photo.
+const FLAT_FIELD_WIDTH: u32 = 32;
+/// Largest gain of a flat field, so that the dark rim of the adapter tube is not blown
+/// up into noise.
+const MAX_GAIN: f32 = 4.0;
+
+/// Per channel gains undoing the vignetting and colour shading of a scope, measured on a
+/// photo of a uniform white reference.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlatField {
+    pub width: u32,
+    pub height: u32,
+    /// Gains of the cells of the map, row by row.
+    pub gains: Vec<[f32; 3]>,
+}
+
+impl FlatField {
+    /// Gains bringing every area of the reference photo to the brightness of its
+    /// brightest area.
+    pub fn from_reference(reference: &DynamicImage) -> Self {
+        let width = FLAT_FIELD_WIDTH.min(reference.width());
+        let height = ((reference.height() as f32) * (width as f32) / (reference.width() as f32))
+            .max(1.0) as u32;
+        let small = reference.thumbnail_exact(width, height).to_rgb8();
+
+        let mut brightest = [1.0f32; 3];
+        for pixel in small.pixels() {
+            for channel in 0..3 {
+                brightest[channel] = brightest[channel].max(pixel[channel] as f32);
+            }
+        }
+        let gains = small
+            .pixels()
+            .map(|pixel| {
+                let mut gain = [1.0; 3];
+                for channel in 0..3 {
+                    gain[channel] =
+                        (brightest[channel] / (pixel[channel] as f32).max(1.0)).min(MAX_GAIN);
+                }
+                gain
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            gains,
+        }
+    }
+
+    /// Gain at a position given as fractions of the photo size, interpolated between
+    /// the centers of the cells.
+    fn gain(&self, u: f32, v: f32) -> [f32; 3] {
+        let x = (u * self.width as f32 - 0.5).clamp(0.0, (self.width - 1) as f32);
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let cell = |x: u32, y: u32| self.gains[(y * self.width + x) as usize];
+
+        let mut gain = [0.0; 3];
+        for (channel, value) in gain.iter_mut().enumerate() {
+            let top = cell(x0, y0)[channel] * (1.0 - fx) + cell(x1, y0)[channel] * fx;
+            let bottom = cell(x0, y1)[channel] * (1.0 - fx) + cell(x1, y1)[channel] * fx;
+            *value = top * (1.0 - fy) + bottom * fy;
+        }
+        gain
+    }
+
+    pub fn apply(&self, image: &mut RgbImage) {
+        let (width, height) = image.dimensions();
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let gain = self.gain(
+                (x as f32 + 0.5) / width as f32,
+                (y as f32 + 0.5) / height as f32,
+            );
+            for channel in 0..3 {
+                pixel[channel] = (pixel[channel] as f32 * gain[channel]).min(255.0) as u8;
+            }
+        }
+    }
+}
+
+/// Applies the corrections of a calibration profile to a photo: the flat field first,
+/// as vignetting follows the sensor, then the colour correction of the visit and the
+/// radial undistortion.
+///
+/// The corrected photo keeps the size and the scale of the original, so that its pixels
+/// map to millimetres with the `px_per_mm` of the profile.
+pub fn correct_photo(
+    image: &DynamicImage,
+    profile: &CalibrationProfile,
+    colour_matrix: Option<&ColourMatrix>,
+) -> RgbImage {
+    let mut corrected = image.to_rgb8();
+    if let Some(flat_field) = &profile.flat_field {
+        flat_field.apply(&mut corrected);
+    }
+    if let Some(colour_matrix) = colour_matrix {
+        colour_matrix.apply(&mut corrected);
+    }
+    if profile.k1 != 0.0 || profile.k2 != 0.0 {
+        corrected = undistort(&corrected, profile);
+    }
+    corrected
+}
+
+fn undistort(image: &RgbImage, profile: &CalibrationProfile) -> RgbImage {
+    let half_width = image.width() as f32 / 2.0;
+    let half_height = image.height() as f32 / 2.0;
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let point = [
+            (x as f32 + 0.5 - half_width - profile.center[0]) / profile.px_per_mm,
+            (half_height - y as f32 - 0.5 - profile.center[1]) / profile.px_per_mm,
+        ];
+        let source = profile.distort(point);
+        sample(
+            image,
+            source[0] + half_width - 0.5,
+            half_height - source[1] - 0.5,
+        )
+    })
+}
+
+/// Bilinear sample of an image at pixel coordinates; black outside of it.
+pub fn sample(image: &RgbImage, x: f32, y: f32) -> Rgb<u8> {
+    let (width, height) = image.dimensions();
+    if x < 0.0 || y < 0.0 || x > (width - 1) as f32 || y > (height - 1) as f32 {
+        return Rgb([0, 0, 0]);
+    }
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+    let mut result = [0u8; 3];
+    for (channel, value) in result.iter_mut().enumerate() {
+        let at = |x: u32, y: u32| image.get_pixel(x, y)[channel] as f32;
+        let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+        let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+        *value = (top * (1.0 - fy) + bottom * fy).round() as u8;
+    }
+    Rgb(result)
+}
+
+#[test]
+fn test_flat_field() {
+    // Vignetted white: darker towards the left edge.
+    let reference = RgbImage::from_fn(64, 32, |x, _| {
+        let value = (128 + x * 2) as u8;
+        Rgb([value, value, value])
+    });
+    let flat_field = FlatField::from_reference(&DynamicImage::ImageRgb8(reference.clone()));
+    let mut corrected = reference;
+    flat_field.apply(&mut corrected);
+    for pixel in corrected.pixels() {
+        assert!(pixel[0] >= 248, "{:?}", pixel);
+    }
+}
+
+#[test]
+fn test_undistort() {
+    let profile = CalibrationProfile {
+        px_per_mm: 10.0,
+        k1: 0.01,
+        ..Default::default()
+    };
+    // A dot 2 mm right of the center, imaged by a lens with pincushion distortion.
+    let distorted = profile.distort([2.0, 0.0]);
+    let image = RgbImage::from_fn(64, 64, |x, y| {
+        let dx = x as f32 + 0.5 - 32.0 - distorted[0];
+        let dy = 32.0 - y as f32 - 0.5 - distorted[1];
+        if dx * dx + dy * dy <= 4.0 {
+            Rgb([255, 255, 255])
+        } else {
+            Rgb([0, 0, 0])
+        }
+    });
+    let corrected = correct_photo(&DynamicImage::ImageRgb8(image), &profile, None);
+    let (mut sum, mut count) = (0.0, 0.0);
+    for (x, _, pixel) in corrected.enumerate_pixels() {
+        sum += (x as f32 + 0.5 - 32.0) * pixel[0] as f32;
+        count += pixel[0] as f32;
+    }
+    assert!((sum / count - 20.0).abs() < 0.5, "{}", sum / count);
+}
@@ -12,6 +12,12 @@ pub enum DScopeError {
     CannotDecodeImage { error: ImageError, file: String },
     CannotCreateImage { error: String, file: String },
     CannotDecodeInfo { error: JsonError, file: String },
+    UnsupportedInfoVersion { version: u64, file: String },
+    InfoRestoredFromBackup { error: String, file: String },
+    CannotCreateDirectory { error: IoError, path: String },
+    CannotRegisterPhoto { file: String },
+    CannotDetectChanges { file: String },
+    UnverifiedCopy { path: String, summary: String },
 }
 
 impl std::fmt::Display for DScopeError {
@@ -41,6 +47,29 @@ impl std::fmt::Display for DScopeError {
             DScopeError::CannotDecodeInfo { error, file } => {
                 f.write_fmt(format_args!("Cannot decode info {}: {}", file, error))
             }
+            DScopeError::UnsupportedInfoVersion { version, file } => f.write_fmt(format_args!(
+                "Cannot decode info {}: version {} was written by a newer release",
+                file, version
+            )),
+            DScopeError::InfoRestoredFromBackup { error, file } => f.write_fmt(format_args!(
+                "Restored info {} from its backup, as it cannot be used: {}",
+                file, error
+            )),
+            DScopeError::CannotCreateDirectory { error, path } => {
+                f.write_fmt(format_args!("Cannot create directory {}: {}", path, error))
+            }
+            DScopeError::CannotRegisterPhoto { file } => f.write_fmt(format_args!(
+                "Cannot register photo {}: no match with the compared photo",
+                file
+            )),
+            DScopeError::CannotDetectChanges { file } => f.write_fmt(format_args!(
+                "Cannot detect changes of photo {}: both photos must be measured",
+                file
+            )),
+            DScopeError::UnverifiedCopy { path, summary } => f.write_fmt(format_args!(
+                "Kept the files at {}, as their copies do not all match:\n{}",
+                path, summary
+            )),
         }
     }
 }
@@ -72,6 +101,27 @@ impl DScopeError {
     pub fn cannot_decode_info(error: JsonError, file: String) -> Self {
         Self::CannotDecodeInfo { error, file }
     }
+    pub fn unsupported_info_version(version: u64, file: String) -> Self {
+        Self::UnsupportedInfoVersion { version, file }
+    }
+    pub fn info_restored_from_backup(error: DScopeError, file: String) -> Self {
+        Self::InfoRestoredFromBackup {
+            error: error.to_string(),
+            file,
+        }
+    }
+    pub fn cannot_create_directory(error: IoError, path: String) -> Self {
+        Self::CannotCreateDirectory { error, path }
+    }
+    pub fn cannot_register_photo(file: String) -> Self {
+        Self::CannotRegisterPhoto { file }
+    }
+    pub fn cannot_detect_changes(file: String) -> Self {
+        Self::CannotDetectChanges { file }
+    }
+    pub fn unverified_copy(path: String, summary: String) -> Self {
+        Self::UnverifiedCopy { path, summary }
+    }
 
     pub fn show(&self) {
         rfd::MessageDialog::new()
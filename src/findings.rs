@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+/// Seven-point checklist score from which a lesion should be excised.
+pub const SEVEN_POINT_SUSPICIOUS: u8 = 3;
+
+/// Dermoscopic criteria of the 7-point checklist observed in a photo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct SevenPointChecklist {
+    pub atypical_network: bool,
+    pub blue_white_veil: bool,
+    pub atypical_vascular_pattern: bool,
+    pub irregular_streaks: bool,
+    pub irregular_dots_globules: bool,
+    pub irregular_blotches: bool,
+    pub regression_structures: bool,
+}
+
+impl SevenPointChecklist {
+    /// The criteria with their names and weights: 2 for the major criteria, 1 for the
+    /// minor ones.
+    pub fn criteria_mut(&mut self) -> [(&'static str, u8, &mut bool); 7] {
+        [
+            ("Atypical pigment network", 2, &mut self.atypical_network),
+            ("Blue-white veil", 2, &mut self.blue_white_veil),
+            (
+                "Atypical vascular pattern",
+                2,
+                &mut self.atypical_vascular_pattern,
+            ),
+            ("Irregular streaks", 1, &mut self.irregular_streaks),
+            (
+                "Irregular dots/globules",
+                1,
+                &mut self.irregular_dots_globules,
+            ),
+            ("Irregular blotches", 1, &mut self.irregular_blotches),
+            ("Regression structures", 1, &mut self.regression_structures),
+        ]
+    }
+
+    pub fn score(&self) -> u8 {
+        let mut checklist = *self;
+        checklist
+            .criteria_mut()
+            .iter()
+            .filter(|(_, _, present)| **present)
+            .map(|(_, weight, _)| weight)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn is_suspicious(&self) -> bool {
+        self.score() >= SEVEN_POINT_SUSPICIOUS
+    }
+}
+
+#[test]
+fn test_seven_point_score() {
+    let mut checklist = SevenPointChecklist::default();
+    assert_eq!(checklist.score(), 0);
+    assert!(checklist.is_empty());
+
+    checklist.blue_white_veil = true;
+    checklist.irregular_streaks = true;
+    assert_eq!(checklist.score(), 3);
+    assert!(checklist.is_suspicious());
+
+    let decoded: SevenPointChecklist =
+        serde_json::from_str(r#"{"irregular_blotches": true}"#).unwrap();
+    assert_eq!(decoded.score(), 1);
+}
@@ -0,0 +1,13 @@
+use image::RgbImage;
+
+/// A round dark lesion on plain skin, shared by the tests of the image analyses.
+pub fn lesion_image(width: u32, height: u32, center: (f32, f32), radius: f32) -> RgbImage {
+    RgbImage::from_fn(width, height, |x, y| {
+        let d = ((x as f32 + 0.5 - center.0).powi(2) + (y as f32 + 0.5 - center.1).powi(2)).sqrt();
+        if d <= radius {
+            image::Rgb([90, 60, 40])
+        } else {
+            image::Rgb([220, 180, 160])
+        }
+    })
+}
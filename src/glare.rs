@@ -0,0 +1,32 @@
+use image::RgbImage;
+
+use crate::imaging::Mask;
+
+/// Brightness from which a pixel may be a specular reflection.
+const GLARE_BRIGHTNESS: u8 = 230;
+/// Largest difference between the channels of a specular reflection, white or nearly.
+const GLARE_CHROMA: u8 = 40;
+
+/// Mask of the specular reflections of the contact plate: saturated, colourless spots,
+/// grown by a pixel to cover their halo.
+pub fn glare_mask(image: &RgbImage) -> Mask {
+    Mask::from_fn(image.width(), image.height(), |x, y| {
+        let pixel = image.get_pixel(x, y);
+        let max = pixel[0].max(pixel[1]).max(pixel[2]);
+        let min = pixel[0].min(pixel[1]).min(pixel[2]);
+        max >= GLARE_BRIGHTNESS && max - min <= GLARE_CHROMA
+    })
+    .dilate(1)
+}
+
+#[test]
+fn test_glare_mask() {
+    let mut image = RgbImage::from_pixel(16, 16, image::Rgb([220, 180, 160]));
+    image.put_pixel(8, 8, image::Rgb([250, 250, 245]));
+    // Bright but coloured: skin under strong light, not a reflection.
+    image.put_pixel(2, 2, image::Rgb([255, 200, 170]));
+    let mask = glare_mask(&image);
+    assert!(mask.get(8, 8) && mask.get(9, 9));
+    assert!(!mask.get(2, 2));
+    assert_eq!(mask.count(), 9);
+}
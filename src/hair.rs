@@ -0,0 +1,181 @@
+use image::{GrayImage, Luma, RgbImage};
+
+use crate::imaging::Mask;
+
+/// Width photos are reduced to before looking for hair; the mask is then scaled back.
+const HAIR_DETECTION_WIDTH: u32 = 1024;
+/// Length of the line structuring elements, as a fraction of the photo width; longer
+/// than hairs are thick, shorter than lesions are wide.
+const HAIR_LINE_FRACTION: f32 = 1.0 / 40.0;
+/// Darkness of a hair relative to the skin around it.
+const HAIR_CONTRAST: u8 = 20;
+/// Directions of the line structuring elements.
+const DIRECTIONS: [(i64, i64); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+/// Removes hair from a photo, DullRazor style: hair is found as thin dark lines with a
+/// morphological black-hat and replaced by the colour of the skin around it.
+pub fn remove_hair(image: &RgbImage) -> RgbImage {
+    let mask = hair_mask(image);
+    let mut result = image.clone();
+    inpaint(&mut result, &mask);
+    result
+}
+
+/// Mask of the hair in a photo of any size.
+pub fn hair_mask(image: &RgbImage) -> Mask {
+    let (width, height) = image.dimensions();
+    if width <= HAIR_DETECTION_WIDTH {
+        return detect_hair(image);
+    }
+    let small_height = ((height as f32) * (HAIR_DETECTION_WIDTH as f32) / (width as f32)) as u32;
+    let small = image::imageops::thumbnail(image, HAIR_DETECTION_WIDTH, small_height.max(1));
+    let small_mask = detect_hair(&small);
+    Mask::from_fn(width, height, |x, y| {
+        small_mask.get(
+            (x as u64 * small_mask.width as u64 / width as u64) as u32,
+            (y as u64 * small_mask.height as u64 / height as u64) as u32,
+        )
+    })
+    .dilate(1)
+}
+
+fn detect_hair(image: &RgbImage) -> Mask {
+    let (width, height) = image.dimensions();
+    let gray = GrayImage::from_fn(width, height, |x, y| {
+        let pixel = image.get_pixel(x, y);
+        Luma([((pixel[0] as u32 * 3 + pixel[1] as u32 * 6 + pixel[2] as u32) / 10) as u8])
+    });
+    let radius = ((width as f32 * HAIR_LINE_FRACTION) as i64 / 2).max(2);
+
+    // Closing along each direction fills hair crossing it; the largest closing fills hair
+    // in any direction, but not the lesion, wider than the lines.
+    let mut closed = gray.clone();
+    for direction in DIRECTIONS {
+        let closing = line_filter(
+            &line_filter(&gray, direction, radius, u8::max),
+            direction,
+            radius,
+            u8::min,
+        );
+        for (pixel, value) in closed.pixels_mut().zip(closing.pixels()) {
+            pixel[0] = pixel[0].max(value[0]);
+        }
+    }
+    let dark = Mask::from_fn(width, height, |x, y| {
+        closed.get_pixel(x, y)[0].saturating_sub(gray.get_pixel(x, y)[0]) >= HAIR_CONTRAST
+    });
+
+    // Dots and globules are filled too; hair is what is longer than the lines.
+    let mut mask = Mask::new(width, height);
+    for component in dark.components() {
+        let (min_x, max_x) = component
+            .pixels
+            .iter()
+            .fold((u32::MAX, 0), |(min, max), (x, _)| {
+                (min.min(*x), max.max(*x))
+            });
+        let (min_y, max_y) = component
+            .pixels
+            .iter()
+            .fold((u32::MAX, 0), |(min, max), (_, y)| {
+                (min.min(*y), max.max(*y))
+            });
+        if (max_x - min_x).max(max_y - min_y) as i64 > 2 * radius {
+            for (x, y) in component.pixels.iter() {
+                mask.set(*x, *y, true);
+            }
+        }
+    }
+    mask.dilate(1)
+}
+
+/// Maximum or minimum of the pixels along a line centered on each pixel.
+fn line_filter(
+    image: &GrayImage,
+    direction: (i64, i64),
+    radius: i64,
+    pick: fn(u8, u8) -> u8,
+) -> GrayImage {
+    let (width, height) = image.dimensions();
+    GrayImage::from_fn(width, height, |x, y| {
+        let mut value = image.get_pixel(x, y)[0];
+        for step in -radius..=radius {
+            let sx = (x as i64 + step * direction.0).clamp(0, width as i64 - 1);
+            let sy = (y as i64 + step * direction.1).clamp(0, height as i64 - 1);
+            value = pick(value, image.get_pixel(sx as u32, sy as u32)[0]);
+        }
+        Luma([value])
+    })
+}
+
+/// Fills the masked pixels from the outside in, each with the average of its already
+/// known neighbours.
+pub fn inpaint(image: &mut RgbImage, mask: &Mask) {
+    let (width, height) = image.dimensions();
+    let mut unknown = mask.clone();
+    let mut remaining = unknown.count();
+    while remaining > 0 {
+        let mut filled = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                if !unknown.get(x, y) {
+                    continue;
+                }
+                let mut sum = [0u32; 3];
+                let mut count = 0;
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                        if (dx, dy) == (0, 0)
+                            || nx < 0
+                            || ny < 0
+                            || nx >= width as i64
+                            || ny >= height as i64
+                            || unknown.get(nx as u32, ny as u32)
+                        {
+                            continue;
+                        }
+                        let pixel = image.get_pixel(nx as u32, ny as u32);
+                        for channel in 0..3 {
+                            sum[channel] += pixel[channel] as u32;
+                        }
+                        count += 1;
+                    }
+                }
+                if count > 0 {
+                    filled.push((x, y, sum.map(|value| (value / count) as u8)));
+                }
+            }
+        }
+        if filled.is_empty() {
+            break;
+        }
+        for (x, y, colour) in filled.iter() {
+            image.put_pixel(*x, *y, image::Rgb(*colour));
+            unknown.set(*x, *y, false);
+        }
+        remaining -= filled.len();
+    }
+}
+
+#[test]
+fn test_remove_hair() {
+    let skin = image::Rgb([220, 180, 160]);
+    let mut image = crate::fixtures::lesion_image(128, 128, (64.0, 64.0), 20.0);
+    // A hair across the photo and a globule, smaller than the structuring lines.
+    for x in 0..128 {
+        for y in 100..102 {
+            image.put_pixel(x, y, image::Rgb([30, 20, 20]));
+        }
+    }
+    image.put_pixel(20, 20, image::Rgb([30, 20, 20]));
+
+    let mask = hair_mask(&image);
+    assert!(mask.get(50, 100));
+    assert!(!mask.get(20, 20));
+    assert!(!mask.get(64, 64));
+
+    let cleaned = remove_hair(&image);
+    assert_eq!(*cleaned.get_pixel(50, 101), skin);
+    assert_eq!(*cleaned.get_pixel(64, 64), *image.get_pixel(64, 64));
+}
@@ -0,0 +1,471 @@
+use std::collections::VecDeque;
+
+/// A binary image, used for lesion and artifact masks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mask {
+    pub width: u32,
+    pub height: u32,
+    data: Vec<bool>,
+}
+
+impl Mask {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![false; (width * height) as usize],
+        }
+    }
+
+    pub fn from_fn(width: u32, height: u32, f: impl Fn(u32, u32) -> bool) -> Self {
+        let mut mask = Self::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                mask.set(x, y, f(x, y));
+            }
+        }
+        mask
+    }
+
+    /// Pixels whose center lies inside the polygon (even-odd rule), in pixel coordinates.
+    pub fn from_polygon(width: u32, height: u32, polygon: &[[f32; 2]]) -> Self {
+        let mut mask = Self::new(width, height);
+        if polygon.len() < 3 {
+            return mask;
+        }
+        for y in 0..height {
+            let py = y as f32 + 0.5;
+            let mut crossings: Vec<f32> = Vec::new();
+            for (index, p1) in polygon.iter().enumerate() {
+                let p2 = polygon[(index + 1) % polygon.len()];
+                if (p1[1] <= py) != (p2[1] <= py) {
+                    crossings.push(p1[0] + (py - p1[1]) / (p2[1] - p1[1]) * (p2[0] - p1[0]));
+                }
+            }
+            crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            for span in crossings.chunks(2) {
+                if let [start, end] = span {
+                    let first = (start - 0.5).ceil().max(0.0) as u32;
+                    let last = (end - 0.5).ceil().min(width as f32) as u32;
+                    for x in first..last {
+                        mask.set(x, y, true);
+                    }
+                }
+            }
+        }
+        mask
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        self.data[(y * self.width + x) as usize]
+    }
+
+    /// Like `get`, but pixels outside of the mask are unset.
+    pub fn get_signed(&self, x: i64, y: i64) -> bool {
+        x >= 0
+            && y >= 0
+            && x < self.width as i64
+            && y < self.height as i64
+            && self.get(x as u32, y as u32)
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, value: bool) {
+        self.data[(y * self.width + x) as usize] = value;
+    }
+
+    pub fn count(&self) -> usize {
+        self.data.iter().filter(|v| **v).count()
+    }
+
+    /// Pixels set in either mask, of the same size.
+    pub fn union(&self, other: &Mask) -> Mask {
+        Mask {
+            width: self.width,
+            height: self.height,
+            data: self
+                .data
+                .iter()
+                .zip(other.data.iter())
+                .map(|(a, b)| *a || *b)
+                .collect(),
+        }
+    }
+
+    /// Coordinates of the set pixels, row by row.
+    pub fn pixels(&self) -> Vec<(u32, u32)> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|(x, y)| self.get(*x, *y))
+            .collect()
+    }
+
+    /// Dilation with a square structuring element of side `2 * radius + 1`.
+    pub fn dilate(&self, radius: u32) -> Mask {
+        self.square_filter(radius, true)
+    }
+
+    /// Erosion with a square structuring element of side `2 * radius + 1`.
+    pub fn erode(&self, radius: u32) -> Mask {
+        self.square_filter(radius, false)
+    }
+
+    pub fn open(&self, radius: u32) -> Mask {
+        self.erode(radius).dilate(radius)
+    }
+
+    pub fn close(&self, radius: u32) -> Mask {
+        self.dilate(radius).erode(radius)
+    }
+
+    /// Separable min/max filter; `any` selects dilation, otherwise erosion.
+    fn square_filter(&self, radius: u32, any: bool) -> Mask {
+        let r = radius as i64;
+        let horizontal = Mask::from_fn(self.width, self.height, |x, y| {
+            let mut window = (-r..=r).map(|dx| self.get_clamped(x as i64 + dx, y as i64));
+            if any {
+                window.any(|v| v)
+            } else {
+                window.all(|v| v)
+            }
+        });
+        Mask::from_fn(self.width, self.height, |x, y| {
+            let mut window = (-r..=r).map(|dy| horizontal.get_clamped(x as i64, y as i64 + dy));
+            if any {
+                window.any(|v| v)
+            } else {
+                window.all(|v| v)
+            }
+        })
+    }
+
+    fn get_clamped(&self, x: i64, y: i64) -> bool {
+        let x = x.clamp(0, self.width as i64 - 1) as u32;
+        let y = y.clamp(0, self.height as i64 - 1) as u32;
+        self.get(x, y)
+    }
+
+    /// Sets every unset region that is not connected to the border of the mask.
+    pub fn fill_holes(&self) -> Mask {
+        let mut outside = Mask::new(self.width, self.height);
+        let mut queue = VecDeque::new();
+        for x in 0..self.width {
+            queue.push_back((x, 0));
+            queue.push_back((x, self.height - 1));
+        }
+        for y in 0..self.height {
+            queue.push_back((0, y));
+            queue.push_back((self.width - 1, y));
+        }
+        while let Some((x, y)) = queue.pop_front() {
+            if self.get(x, y) || outside.get(x, y) {
+                continue;
+            }
+            outside.set(x, y, true);
+            self.for_each_neighbour(x, y, |nx, ny| queue.push_back((nx, ny)));
+        }
+        Mask::from_fn(self.width, self.height, |x, y| !outside.get(x, y))
+    }
+
+    /// Splits the set pixels into 4-connected components.
+    pub fn components(&self) -> Vec<Component> {
+        let mut visited = Mask::new(self.width, self.height);
+        let mut components = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !self.get(x, y) || visited.get(x, y) {
+                    continue;
+                }
+                let mut pixels = Vec::new();
+                let mut queue = VecDeque::from([(x, y)]);
+                visited.set(x, y, true);
+                while let Some((px, py)) = queue.pop_front() {
+                    pixels.push((px, py));
+                    self.for_each_neighbour(px, py, |nx, ny| {
+                        if self.get(nx, ny) && !visited.get(nx, ny) {
+                            visited.set(nx, ny, true);
+                            queue.push_back((nx, ny));
+                        }
+                    });
+                }
+                components.push(Component { pixels });
+            }
+        }
+        components
+    }
+
+    fn for_each_neighbour(&self, x: u32, y: u32, mut f: impl FnMut(u32, u32)) {
+        if x > 0 {
+            f(x - 1, y);
+        }
+        if y > 0 {
+            f(x, y - 1);
+        }
+        if x + 1 < self.width {
+            f(x + 1, y);
+        }
+        if y + 1 < self.height {
+            f(x, y + 1);
+        }
+    }
+
+    /// Outer contour of the first component in raster order, as the corners of the
+    /// pixel edges walked clockwise (with the Y axis pointing down).
+    ///
+    /// The polygon encloses exactly the pixels of a component without holes.
+    pub fn contour(&self) -> Vec<[i64; 2]> {
+        let start = match (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .find(|(x, y)| self.get(*x, *y))
+        {
+            Some((x, y)) => [x as i64, y as i64],
+            None => return Vec::new(),
+        };
+
+        // Directions on the corner grid: east, south, west, north.
+        const DIRECTIONS: [[i64; 2]; 4] = [[1, 0], [0, 1], [-1, 0], [0, -1]];
+        // For a step from a corner, the offsets of the pixels at its left and right.
+        const LEFT: [[i64; 2]; 4] = [[0, -1], [0, 0], [-1, 0], [-1, -1]];
+        const RIGHT: [[i64; 2]; 4] = [[0, 0], [-1, 0], [-1, -1], [0, -1]];
+
+        let mut corners = vec![start];
+        let mut position = start;
+        let mut direction = 0;
+        loop {
+            position = [
+                position[0] + DIRECTIONS[direction][0],
+                position[1] + DIRECTIONS[direction][1],
+            ];
+            if position == start {
+                break;
+            }
+            let pixel = |offset: [i64; 2]| {
+                self.get_signed(position[0] + offset[0], position[1] + offset[1])
+            };
+            let next = if !pixel(RIGHT[direction]) {
+                (direction + 1) % 4
+            } else if pixel(LEFT[direction]) {
+                (direction + 3) % 4
+            } else {
+                direction
+            };
+            if next != direction {
+                corners.push(position);
+                direction = next;
+            }
+        }
+        corners
+    }
+}
+
+pub struct Component {
+    pub pixels: Vec<(u32, u32)>,
+}
+
+impl Component {
+    pub fn area(&self) -> usize {
+        self.pixels.len()
+    }
+
+    pub fn touches(&self, mask: &Mask) -> bool {
+        self.pixels.iter().any(|(x, y)| mask.get(*x, *y))
+    }
+
+    pub fn to_mask(&self, width: u32, height: u32) -> Mask {
+        let mut mask = Mask::new(width, height);
+        for (x, y) in self.pixels.iter() {
+            mask.set(*x, *y, true);
+        }
+        mask
+    }
+}
+
+/// Centroid and principal axis orientation of a mask.
+pub struct Moments {
+    pub area: f32,
+    /// Centroid, in pixels.
+    pub cx: f32,
+    pub cy: f32,
+    /// Angle of the principal axis from the X axis, towards the Y axis (downwards).
+    pub angle: f32,
+}
+
+impl Moments {
+    pub fn new(mask: &Mask) -> Option<Self> {
+        let pixels = mask.pixels();
+        if pixels.len() < 16 {
+            return None;
+        }
+        let area = pixels.len() as f32;
+        let cx = pixels.iter().map(|(x, _)| *x as f32 + 0.5).sum::<f32>() / area;
+        let cy = pixels.iter().map(|(_, y)| *y as f32 + 0.5).sum::<f32>() / area;
+        let (mut mu20, mut mu02, mut mu11) = (0.0, 0.0, 0.0);
+        for (x, y) in pixels.iter() {
+            let dx = *x as f32 + 0.5 - cx;
+            let dy = *y as f32 + 0.5 - cy;
+            mu20 += dx * dx;
+            mu02 += dy * dy;
+            mu11 += dx * dy;
+        }
+        Some(Self {
+            area,
+            cx,
+            cy,
+            angle: 0.5 * (2.0 * mu11).atan2(mu20 - mu02),
+        })
+    }
+}
+
+/// Otsu's threshold of a set of samples: values up to the threshold form the dark class.
+pub fn otsu_threshold(values: impl Iterator<Item = u8>) -> u8 {
+    let mut histogram = [0u64; 256];
+    let mut total = 0u64;
+    for value in values {
+        histogram[value as usize] += 1;
+        total += 1;
+    }
+    if total == 0 {
+        return 0;
+    }
+
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, count)| value as f64 * *count as f64)
+        .sum();
+    let mut best = (0.0, 0u8);
+    let mut dark_count = 0u64;
+    let mut dark_sum = 0.0;
+    for (value, count) in histogram.iter().enumerate() {
+        dark_count += count;
+        if dark_count == 0 {
+            continue;
+        }
+        let light_count = total - dark_count;
+        if light_count == 0 {
+            break;
+        }
+        dark_sum += value as f64 * *count as f64;
+        let dark_mean = dark_sum / dark_count as f64;
+        let light_mean = (sum - dark_sum) / light_count as f64;
+        let variance = dark_count as f64 * light_count as f64 * (dark_mean - light_mean).powi(2);
+        if variance > best.0 {
+            best = (variance, value as u8);
+        }
+    }
+    best.1
+}
+
+/// Douglas-Peucker simplification of a closed polygon.
+pub fn simplify_polygon(points: &[[f32; 2]], epsilon: f32) -> Vec<[f32; 2]> {
+    if points.len() < 4 {
+        return points.to_vec();
+    }
+    let far = (1..points.len())
+        .max_by(|a, b| {
+            squared_distance(points[0], points[*a])
+                .partial_cmp(&squared_distance(points[0], points[*b]))
+                .unwrap()
+        })
+        .unwrap();
+
+    let mut result = simplify_open(&points[..=far], epsilon);
+    result.pop();
+    let mut closing = points[far..].to_vec();
+    closing.push(points[0]);
+    result.extend(simplify_open(&closing, epsilon));
+    result.pop();
+    result
+}
+
+fn simplify_open(points: &[[f32; 2]], epsilon: f32) -> Vec<[f32; 2]> {
+    let first = points[0];
+    let last = points[points.len() - 1];
+    let (index, distance) = points[1..points.len() - 1]
+        .iter()
+        .enumerate()
+        .map(|(index, point)| (index + 1, segment_distance(*point, first, last)))
+        .fold((0, 0.0), |best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        });
+    if distance > epsilon {
+        let mut result = simplify_open(&points[..=index], epsilon);
+        result.pop();
+        result.extend(simplify_open(&points[index..], epsilon));
+        result
+    } else {
+        vec![first, last]
+    }
+}
+
+fn squared_distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)
+}
+
+fn segment_distance(point: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    let length = squared_distance(a, b);
+    if length == 0.0 {
+        return squared_distance(point, a).sqrt();
+    }
+    let cross = (b[0] - a[0]) * (point[1] - a[1]) - (b[1] - a[1]) * (point[0] - a[0]);
+    cross.abs() / length.sqrt()
+}
+
+#[test]
+fn test_mask_morphology() {
+    let mut mask = Mask::new(9, 9);
+    mask.set(4, 4, true);
+    assert_eq!(mask.dilate(1).count(), 9);
+    assert_eq!(mask.dilate(1).erode(1), mask);
+    assert_eq!(mask.open(1).count(), 0);
+
+    let ring = Mask::from_fn(9, 9, |x, y| {
+        (2..=6).contains(&x) && (2..=6).contains(&y) && (x, y) != (4, 4)
+    });
+    assert_eq!(ring.fill_holes().count(), 25);
+    assert_eq!(ring.components().len(), 1);
+}
+
+#[test]
+fn test_mask_contour() {
+    let mask = Mask::from_fn(10, 10, |x, y| {
+        ((x as i32 - 5).pow(2) + (y as i32 - 5).pow(2)) <= 9 || (x == 5 && y == 1)
+    });
+    let contour = mask.contour();
+    let points: Vec<[f32; 2]> = contour.iter().map(|p| [p[0] as f32, p[1] as f32]).collect();
+    assert_eq!(crate::outline::polygon_area(&points) as usize, mask.count());
+}
+
+#[test]
+fn test_mask_from_polygon() {
+    let square = [[1.0, 1.0], [5.0, 1.0], [5.0, 4.0], [1.0, 4.0]];
+    let mask = Mask::from_polygon(8, 8, &square);
+    assert_eq!(mask.count(), 12);
+    assert!(mask.get(1, 1) && mask.get(4, 3));
+    assert!(!mask.get(5, 3) && !mask.get(0, 0));
+}
+
+#[test]
+fn test_otsu_threshold() {
+    let values = [10u8, 12, 11, 200, 190, 210, 205];
+    let threshold = otsu_threshold(values.iter().copied());
+    assert!((12..190).contains(&threshold));
+}
+
+#[test]
+fn test_simplify_polygon() {
+    let square = [
+        [0.0, 0.0],
+        [1.0, 0.0],
+        [2.0, 0.0],
+        [2.0, 1.0],
+        [2.0, 2.0],
+        [1.0, 2.0],
+        [0.0, 2.0],
+        [0.0, 1.0],
+    ];
+    assert_eq!(simplify_polygon(&square, 0.1).len(), 4);
+}
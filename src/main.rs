@@ -1,24 +1,76 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
+mod abcd;
+mod archive;
+mod body_map;
+mod burst;
+mod calibration;
+mod change;
+mod colour;
+mod compare;
+mod correction;
 mod errors;
+mod findings;
+#[cfg(test)]
+mod fixtures;
+mod glare;
+mod hair;
+mod imaging;
+mod mole;
+mod outline;
+mod overview;
+mod panorama;
 mod photo_set;
+mod quality;
+mod registration;
+mod relocation;
+mod segmentation;
+mod stacking;
+mod timeline;
 
-use std::{f32::consts::PI, path::PathBuf};
+use std::{
+    f32::consts::PI,
+    path::{Path, PathBuf},
+};
 
+use abcd::{compute_abcd, STRUCTURES_MAX};
+use archive::{Archive, Patient};
+use body_map::{marker_at, BodyMarker, BodyView};
+use calibration::{
+    detect_dots, fit_calibration, CalibrationLibrary, CalibrationProfile, CalibrationTarget,
+    DEFAULT_TARGET_SPACING,
+};
+use colour::{ColourMatrix, ColourReference, ColourSample, COLOUR_CHECKER};
+use compare::{compare_sources, CompareMode, Comparison};
+use correction::FlatField;
 use eframe::{
     egui::{
         self,
-        plot::{Line, Plot, PlotImage, Value, Values},
-        Button, ImageButton, Slider,
+        plot::{
+            Line, LineStyle, LinkedAxisGroup, Plot, PlotImage, PlotUi, Points, Text, Value, Values,
+        },
+        Align2, Button, CollapsingHeader, ComboBox, ImageButton, Slider,
     },
-    epaint::{Color32, Stroke},
+    epaint::{Color32, ColorImage, Stroke},
 };
 use egui_extras::RetainedImage;
 use errors::DScopeError;
+use findings::SevenPointChecklist;
+use image::{DynamicImage, RgbImage};
+use mole::{MoleId, MoleObservation};
+use outline::{MoleShape, Point};
+use panorama::stitch;
 use photo_set::{
-    photo_file_name, DisplayTime, PhotoSet, MOLE_CENTER_DISTANCE_MAX, MOLE_SIZE_MAX,
-    PHOTO_PX_PER_MM,
+    photo_file_name, retained_image, Derivation, DisplayTime, MoleMetrics, Photo, PhotoSet,
+    PhotoSetInfo,
 };
 
+use stacking::focus_stack;
+use timeline::{timeline_points, TimelineMeasure, DEFAULT_GROWTH_ALERT, GROWTH_ALERT_MAX};
+
+const COMPARED_MEASURES_COLOR: Color32 = Color32::YELLOW;
+const PROPOSAL_COLOR: Color32 = Color32::LIGHT_GREEN;
+const GLARE_OVERLAY_COLOR: [u8; 4] = [0, 255, 255, 160];
+
 fn main() {
     eframe::run_native(
         "D-Scope",
@@ -32,18 +84,99 @@ enum DScopeUi {
     Show {
         photos: PhotoSet,
         current_photo_index: usize,
-        current_photo: RetainedImage,
+        current_photo: PhotoView,
         show_measures: bool,
         edit_measures: bool,
         edit_data: bool,
         save: bool,
-        cleanup: Option<PathBuf>,
+        cleanup: Option<(PathBuf, Vec<String>)>,
+        compare: Option<Comparison>,
+        panorama: Option<Vec<usize>>,
+        overview: Option<OverviewView>,
     },
 }
 
+struct PhotoView {
+    photo: RetainedImage,
+    glare: RetainedImage,
+}
+
+struct OverviewView {
+    index: usize,
+    image: RetainedImage,
+    /// Clicks link the current photo to a lesion instead of selecting photos.
+    mark: bool,
+}
+
+struct NewPatient {
+    name: String,
+    surname: String,
+}
+
+struct NewMole {
+    label: String,
+    location: String,
+}
+
+struct MoleHistory {
+    mole_id: MoleId,
+    observations: Vec<MoleObservation>,
+}
+
+struct BodyMap {
+    view: BodyView,
+    place_mole_id: Option<MoleId>,
+}
+
+struct CalibrationWizard {
+    photo: Option<(RetainedImage, DynamicImage)>,
+    target: CalibrationTarget,
+    spacing: f32,
+    marks: Vec<[f32; 2]>,
+    name: String,
+}
+
+impl Default for CalibrationWizard {
+    fn default() -> Self {
+        Self {
+            photo: None,
+            target: CalibrationTarget::Ruler,
+            spacing: DEFAULT_TARGET_SPACING,
+            marks: Vec::new(),
+            name: String::new(),
+        }
+    }
+}
+
+struct ColourWizard {
+    image: RgbImage,
+    photo: RetainedImage,
+    samples: Vec<ColourSample>,
+    reference: ColourReference,
+}
+
 struct DScopeStatus {
     pub error: Option<DScopeError>,
     pub load: Option<PathBuf>,
+    pub load_photo_id: Option<usize>,
+    pub select_photo_id: Option<usize>,
+    pub open_archive: Option<PathBuf>,
+    pub archive: Option<Archive>,
+    pub current_patient_index: Option<usize>,
+    pub new_patient: Option<NewPatient>,
+    pub add_visit: bool,
+    pub new_mole: Option<NewMole>,
+    pub mole_history: Option<MoleHistory>,
+    pub body_map: Option<BodyMap>,
+    pub growth_alert: f32,
+    pub timeline_measure: TimelineMeasure,
+    pub calibrations: CalibrationLibrary,
+    pub new_calibration: Option<CalibrationProfile>,
+    pub calibration_wizard: Option<CalibrationWizard>,
+    pub colour_wizard: Option<ColourWizard>,
+    pub remove_hair: bool,
+    pub show_glare: bool,
+    pub hide_poor_photos: bool,
     pub ui: DScopeUi,
 }
 
@@ -53,10 +186,33 @@ struct MyApp {
 
 impl Default for MyApp {
     fn default() -> Self {
+        let (calibrations, error) = match CalibrationLibrary::load() {
+            Ok(calibrations) => (calibrations, None),
+            Err(error) => (Default::default(), Some(error)),
+        };
         Self {
             status: DScopeStatus {
-                error: None,
+                error,
                 load: None,
+                load_photo_id: None,
+                select_photo_id: None,
+                open_archive: None,
+                archive: None,
+                current_patient_index: None,
+                new_patient: None,
+                add_visit: false,
+                new_mole: None,
+                mole_history: None,
+                body_map: None,
+                growth_alert: DEFAULT_GROWTH_ALERT,
+                timeline_measure: TimelineMeasure::Diameter,
+                calibrations,
+                new_calibration: None,
+                calibration_wizard: None,
+                colour_wizard: None,
+                remove_hair: false,
+                show_glare: false,
+                hide_poor_photos: false,
                 ui: DScopeUi::Empty,
             },
         }
@@ -68,32 +224,58 @@ impl eframe::App for MyApp {
         if let Some(error) = self.status.error.take() {
             error.show();
         }
+        if let Some(path) = self.status.open_archive.take() {
+            match Archive::from_path(path) {
+                Ok(archive) => {
+                    self.status.archive = Some(archive);
+                    self.status.current_patient_index = None;
+                }
+                Err(error) => self.status.error = Some(error),
+            }
+        }
         if let Some(path) = self.status.load.take() {
             match PhotoSet::from_path(path) {
                 Ok(photos) => {
-                    match RetainedImage::from_image_bytes("selected-photo", &photos.photos[0].bytes)
-                    {
+                    if let Some(error) = &photos.restored_info {
+                        error.show();
+                    }
+                    if let Some(patient_id) = &photos.info.patient_id {
+                        select_visit_patient(&mut self.status, &photos.path, patient_id);
+                    }
+                    let current_photo_index = self
+                        .status
+                        .load_photo_id
+                        .take()
+                        .and_then(|id| photos.photo_index(id))
+                        .or_else(|| photos.bursts().first().map(|burst| burst.representative))
+                        .unwrap_or(0);
+                    match photo_texture(&photos, current_photo_index, self.status.remove_hair) {
                         Ok(current_photo) => {
                             self.status.ui = DScopeUi::Show {
                                 photos,
-                                current_photo_index: 0,
+                                current_photo_index,
                                 current_photo,
                                 show_measures: false,
                                 edit_measures: false,
                                 edit_data: false,
                                 save: false,
                                 cleanup: None,
+                                compare: None,
+                                panorama: None,
+                                overview: None,
                             };
                         }
-                        Err(error) => {
-                            self.status.error =
-                                Some(DScopeError::cannot_create_image(error, photo_file_name(0)))
-                        }
+                        Err(error) => self.status.error = Some(error),
                     }
                 }
                 Err(error) => self.status.error = Some(error),
             }
         }
+        archive_panel(ctx, &mut self.status);
+        mole_history_window(ctx, &mut self.status);
+        body_map_window(ctx, &mut self.status);
+        calibration_window(ctx, &mut self.status);
+        colour_window(ctx, &mut self.status);
         match &mut self.status.ui {
             DScopeUi::Empty => {
                 egui::TopBottomPanel::top("toolbar").show(ctx, |ui| {
@@ -103,6 +285,14 @@ impl eframe::App for MyApp {
                                 self.status.load = Some(path);
                             }
                         }
+                        if ui.button("Archive").clicked() {
+                            if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                                self.status.open_archive = Some(path);
+                            }
+                        }
+                        if ui.button("Calibrate").clicked() {
+                            self.status.calibration_wizard = Some(Default::default());
+                        }
                     })
                 });
             }
@@ -115,6 +305,9 @@ impl eframe::App for MyApp {
                 edit_data,
                 save,
                 cleanup,
+                compare,
+                panorama,
+                overview,
             } => {
                 if *save {
                     *save = false;
@@ -126,22 +319,59 @@ impl eframe::App for MyApp {
                     }
                 }
 
-                if let Some(path) = cleanup.take() {
-                    if rfd::MessageDialog::new()
+                if let Some(id) = self.status.select_photo_id.take() {
+                    if let Some(index) = photos.photo_index(id) {
+                        if let Err(error) = select_photo(
+                            photos,
+                            index,
+                            self.status.remove_hair,
+                            current_photo_index,
+                            current_photo,
+                        ) {
+                            self.status.error = Some(error);
+                        }
+                    }
+                }
+
+                if self.status.add_visit {
+                    self.status.add_visit = false;
+                    if let Some(patient) = self
+                        .status
+                        .archive
+                        .as_mut()
+                        .zip(self.status.current_patient_index)
+                        .map(|(archive, index)| &mut archive.patients[index])
+                    {
+                        match patient.add_visit(photos) {
+                            Ok(moved) => *cleanup = Some(moved),
+                            Err(error) => self.status.error = Some(error),
+                        }
+                    }
+                }
+
+                if let Some((path, kept)) = cleanup.take() {
+                    let relocation = photos.verify_relocation(path, kept);
+                    if !relocation.is_moved() {
+                        // Saved over the folder it was loaded from: nothing to remove.
+                    } else if !relocation.is_verified() {
+                        self.status.error = Some(relocation.refusal());
+                    } else if rfd::MessageDialog::new()
                         .set_title("Notice")
                         .set_description(&format!(
-                            "remove files at '{}'?",
-                            path.to_string_lossy().to_string()
+                            "{}\n\nremove files at '{}'?",
+                            relocation.summary(),
+                            relocation.from.to_string_lossy()
                         ))
                         .set_buttons(rfd::MessageButtons::YesNo)
                         .show()
                     {
-                        if let Err(error) = photos.cleanup(&path) {
+                        if let Err(error) = photos.cleanup(&relocation) {
                             self.status.error = Some(error);
                         }
                     }
                 }
 
+                let mut select_index = None;
                 egui::TopBottomPanel::top("toolbar").show(ctx, |ui| {
                     ui.vertical(|ui| {
                         ui.horizontal(|ui| {
@@ -155,8 +385,8 @@ impl eframe::App for MyApp {
                                     let old_path = photos.path.clone();
                                     photos.path = new_path;
                                     match photos.save() {
-                                        Ok(_) => {
-                                            *cleanup = Some(old_path);
+                                        Ok(kept) => {
+                                            *cleanup = Some((old_path, kept));
                                         }
                                         Err(error) => {
                                             photos.path = old_path;
@@ -165,9 +395,55 @@ impl eframe::App for MyApp {
                                     }
                                 }
                             }
+                            if ui.button("Export").clicked() {
+                                if let Some(path) = rfd::FileDialog::new()
+                                    .add_filter("CSV", &["csv"])
+                                    .save_file()
+                                {
+                                    let patient = visit_patient(
+                                        &mut self.status.archive,
+                                        self.status.current_patient_index,
+                                        photos,
+                                    );
+                                    let mole_name = |mole_id: MoleId| {
+                                        patient
+                                            .as_ref()
+                                            .and_then(|patient| patient.info.mole(mole_id))
+                                            .map(|mole| mole.display_name())
+                                            .unwrap_or_else(|| mole_id.to_string())
+                                    };
+                                    if let Err(error) = photos.export_csv(&path, mole_name) {
+                                        self.status.error = Some(error);
+                                    }
+                                }
+                            }
+                            if ui.button("Archive").clicked() {
+                                if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                                    self.status.open_archive = Some(path);
+                                }
+                            }
+                            if ui.button("Calibrate").clicked() {
+                                self.status.calibration_wizard = Some(Default::default());
+                            }
 
                             ui.separator();
 
+                            if ui
+                                .checkbox(&mut self.status.remove_hair, "Hair removal")
+                                .changed()
+                            {
+                                let index = *current_photo_index;
+                                if let Err(error) = select_photo(
+                                    photos,
+                                    index,
+                                    self.status.remove_hair,
+                                    current_photo_index,
+                                    current_photo,
+                                ) {
+                                    self.status.error = Some(error);
+                                }
+                            }
+                            ui.checkbox(&mut self.status.show_glare, "Glare");
                             ui.checkbox(show_measures, "Metrics");
                             if *show_measures {
                                 if ui
@@ -183,6 +459,98 @@ impl eframe::App for MyApp {
                             {
                                 *edit_data = true;
                             }
+                            if ui
+                                .button("Colour")
+                                .on_hover_text("Correct colours with a reference card photographed during the visit")
+                                .clicked()
+                            {
+                                let photo = &photos.photos[*current_photo_index];
+                                match photo.corrected(&photos.info.calibration, None) {
+                                    Ok(image) => {
+                                        self.status.colour_wizard = Some(ColourWizard {
+                                            photo: retained_image("colour-reference", &image),
+                                            image,
+                                            samples: Vec::new(),
+                                            reference: ColourReference::Neutral,
+                                        })
+                                    }
+                                    Err(error) => self.status.error = Some(error),
+                                }
+                            }
+                            if ui
+                                .add_enabled(compare.is_none(), Button::new("Compare"))
+                                .clicked()
+                            {
+                                let mole_id = photos.photos[*current_photo_index].info.mole_id;
+                                let patient = visit_patient(
+                                    &mut self.status.archive,
+                                    self.status.current_patient_index,
+                                    photos,
+                                );
+                                let history = match patient.zip(mole_id) {
+                                    Some((patient, mole_id)) => patient.mole_history(mole_id),
+                                    None => Vec::new(),
+                                };
+                                let sources =
+                                    compare_sources(photos, *current_photo_index, &history);
+                                match Comparison::new(photos, *current_photo_index, sources) {
+                                    Ok(comparison) => *compare = comparison,
+                                    Err(error) => self.status.error = Some(error),
+                                }
+                            }
+                            if !photos.overviews.is_empty()
+                                && ui
+                                    .add_enabled(overview.is_none(), Button::new("Overview"))
+                                    .on_hover_text("Show the overview photos of the visit")
+                                    .clicked()
+                            {
+                                let photo_id = photos.photos[*current_photo_index].id;
+                                let index = photos
+                                    .overviews
+                                    .iter()
+                                    .position(|overview| overview.info.is_linked(photo_id))
+                                    .unwrap_or(0);
+                                match photos.overviews[index].image() {
+                                    Ok(image) => {
+                                        *overview = Some(OverviewView {
+                                            index,
+                                            image,
+                                            mark: false,
+                                        })
+                                    }
+                                    Err(error) => self.status.error = Some(error),
+                                }
+                            }
+                            match panorama {
+                                None => {
+                                    if ui
+                                        .button("Panorama")
+                                        .on_hover_text("Stitch overlapping photos of a lesion larger than the scope")
+                                        .clicked()
+                                    {
+                                        *panorama = Some(vec![*current_photo_index]);
+                                    }
+                                }
+                                Some(selection) => {
+                                    if ui
+                                        .add_enabled(
+                                            selection.len() > 1,
+                                            Button::new(format!("Stitch {} photos", selection.len())),
+                                        )
+                                        .clicked()
+                                    {
+                                        match stitch_panorama(photos, selection) {
+                                            Ok(index) => {
+                                                select_index = Some(index);
+                                                *panorama = None;
+                                            }
+                                            Err(error) => self.status.error = Some(error),
+                                        }
+                                    } else if ui.button("Cancel").clicked() {
+                                        *panorama = None;
+                                    }
+                                }
+                            }
 
                             ui.separator();
 
@@ -199,10 +567,131 @@ impl eframe::App for MyApp {
                                 DisplayTime::new(current_photo_info.info.time)
                             ));
                             if let Some(size) = current_photo_info.info.mole_metrics.size() {
-                                ui.label(format!("(size {} mm)", size));
+                                match current_photo_info.info.mole_metrics.area() {
+                                    Some(area) => ui
+                                        .label(format!("(size {} mm, area {:.2} mm²)", size, area)),
+                                    None => ui.label(format!("(size {} mm)", size)),
+                                };
+                            }
+                            if let Some(abcd) = current_photo_info.info.abcd {
+                                ui.label(abcd.to_string());
+                            }
+                            if !current_photo_info.info.findings.is_empty() {
+                                ui.label(format!(
+                                    "7-point {}",
+                                    current_photo_info.info.findings.score()
+                                ));
+                            }
+                            if let Some(change) = current_photo_info.info.change {
+                                ui.label(format!("Change: {}", change.measures));
+                            }
+                            if let Some(mole_id) = current_photo_info.info.mole_id {
+                                let patient = visit_patient(
+                                    &mut self.status.archive,
+                                    self.status.current_patient_index,
+                                    photos,
+                                );
+                                if let Some(patient) = patient {
+                                    if let Some(mole) = patient.info.mole(mole_id) {
+                                        ui.label(format!("mole {}", mole.display_name()));
+                                    }
+                                    if ui.button("History").clicked() {
+                                        self.status.mole_history = Some(MoleHistory {
+                                            mole_id,
+                                            observations: patient.mole_history(mole_id),
+                                        });
+                                    }
+                                }
                             }
                         });
 
+                        let mut close_comparison = false;
+                        if let Some(comparison) = compare.as_mut() {
+                            ui.horizontal(|ui| {
+                                ui.label("Compare with");
+                                let mut source_index = comparison.source_index;
+                                ComboBox::from_id_source("compare-source")
+                                    .selected_text(&comparison.source().label)
+                                    .show_ui(ui, |ui| {
+                                        for (index, source) in comparison.sources.iter().enumerate()
+                                        {
+                                            ui.selectable_value(
+                                                &mut source_index,
+                                                index,
+                                                &source.label,
+                                            );
+                                        }
+                                    });
+                                if source_index != comparison.source_index {
+                                    if let Err(error) =
+                                        comparison.select(photos, *current_photo_index, source_index)
+                                    {
+                                        self.status.error = Some(error);
+                                    }
+                                }
+
+                                ui.separator();
+                                if comparison.is_registered(photos, *current_photo_index) {
+                                    if let Some(registration) =
+                                        &photos.photos[*current_photo_index].info.registration
+                                    {
+                                        ui.label(format!(
+                                            "Registered (correlation {:.2})",
+                                            registration.correlation
+                                        ));
+                                    }
+                                    if ui.button("Unregister").clicked() {
+                                        comparison.unregister(photos, *current_photo_index);
+                                    }
+                                } else if ui.button("Register").clicked() {
+                                    if let Err(error) =
+                                        comparison.register(photos, *current_photo_index)
+                                    {
+                                        self.status.error = Some(error);
+                                    }
+                                }
+                                if comparison.is_aligned() {
+                                    if comparison.changes.is_some() {
+                                        if ui.button("Hide changes").clicked() {
+                                            comparison.changes = None;
+                                        }
+                                    } else if ui.button("Changes").clicked() {
+                                        if let Err(error) =
+                                            comparison.detect_changes(photos, *current_photo_index)
+                                        {
+                                            self.status.error = Some(error);
+                                        }
+                                    }
+                                }
+
+                                ui.separator();
+                                ui.radio_value(
+                                    &mut comparison.mode,
+                                    CompareMode::SideBySide,
+                                    "Side by side",
+                                );
+                                ui.radio_value(
+                                    &mut comparison.mode,
+                                    CompareMode::Overlay,
+                                    "Overlay",
+                                );
+                                if comparison.mode == CompareMode::Overlay {
+                                    ui.add_enabled(
+                                        !comparison.flicker,
+                                        Slider::new(&mut comparison.opacity, 0.0..=1.0)
+                                            .text("Opacity"),
+                                    );
+                                    ui.checkbox(&mut comparison.flicker, "Flicker");
+                                }
+
+                                ui.separator();
+                                close_comparison = ui.button("Close").clicked();
+                            });
+                        }
+                        if close_comparison {
+                            *compare = None;
+                        }
+
                         if *edit_data || *edit_measures {
                             let current_photo_info = &mut photos.photos[*current_photo_index];
 
@@ -220,7 +709,48 @@ impl eframe::App for MyApp {
                                     ui.text_edit_multiline(&mut photos.info.notes);
                                     ui.label("Photo notes");
                                     ui.text_edit_multiline(&mut current_photo_info.info.notes);
+                                    ui.checkbox(&mut current_photo_info.info.flagged, "Poor shot");
+                                    ui.separator();
+                                    edit_findings_panel(ui, &mut current_photo_info.info.findings);
                                 });
+                                if edit_calibration_panel(
+                                    ui,
+                                    photos,
+                                    &mut self.status.calibrations,
+                                    &mut self.status.new_calibration,
+                                    &mut self.status.error,
+                                ) {
+                                    // Its measures and changes are under the previous scale.
+                                    *compare = None;
+                                    let index = *current_photo_index;
+                                    if let Err(error) = select_photo(
+                                        photos,
+                                        index,
+                                        self.status.remove_hair,
+                                        current_photo_index,
+                                        current_photo,
+                                    ) {
+                                        self.status.error = Some(error);
+                                    }
+                                }
+                                let patient = visit_patient(
+                                    &mut self.status.archive,
+                                    self.status.current_patient_index,
+                                    photos,
+                                );
+                                let current_photo_info = &mut photos.photos[*current_photo_index];
+                                match patient {
+                                    Some(patient) => edit_photo_mole(
+                                        ui,
+                                        patient,
+                                        &mut current_photo_info.info.mole_id,
+                                        &mut self.status.new_mole,
+                                        &mut self.status.error,
+                                    ),
+                                    None => {
+                                        ui.label("Add the visit to a patient to track its moles");
+                                    }
+                                }
                                 if !*edit_measures {
                                     if ui.button("Save").clicked() {
                                         *save = true;
@@ -229,33 +759,29 @@ impl eframe::App for MyApp {
                             }
 
                             if *edit_measures {
+                                let size = current_photo.photo.size();
+                                let px_per_mm = photos.info.calibration.px_per_mm;
+                                let current_photo_info = &mut photos.photos[*current_photo_index];
                                 ui.separator();
                                 ui.horizontal(|ui| {
-                                    ui.label("X");
-                                    ui.add(
-                                        Slider::new(
-                                            &mut current_photo_info.info.mole_metrics.center_x,
-                                            -MOLE_CENTER_DISTANCE_MAX..=MOLE_CENTER_DISTANCE_MAX,
-                                        )
-                                        .clamp_to_range(true),
+                                    let previous_metrics =
+                                        current_photo_info.info.mole_metrics.clone();
+                                    edit_measures_panel(
+                                        ui,
+                                        &mut current_photo_info.info.mole_metrics,
+                                        current_photo_info.proposal.as_ref(),
+                                        [size[0] as f32 / px_per_mm, size[1] as f32 / px_per_mm],
                                     );
-                                    ui.label("Y");
-                                    ui.separator();
-                                    ui.add(
-                                        Slider::new(
-                                            &mut current_photo_info.info.mole_metrics.center_y,
-                                            -MOLE_CENTER_DISTANCE_MAX..=MOLE_CENTER_DISTANCE_MAX,
-                                        )
-                                        .clamp_to_range(true),
-                                    );
-                                    ui.label("Size");
+                                    // The score was computed inside the previous outline.
+                                    if current_photo_info.info.mole_metrics != previous_metrics {
+                                        current_photo_info.info.abcd = None;
+                                    }
                                     ui.separator();
-                                    ui.add(
-                                        Slider::new(
-                                            &mut current_photo_info.info.mole_metrics.diameter,
-                                            0.0..=MOLE_SIZE_MAX,
-                                        )
-                                        .clamp_to_range(true),
+                                    edit_abcd_panel(
+                                        ui,
+                                        current_photo_info,
+                                        &photos.info,
+                                        &mut self.status.error,
                                     );
                                     ui.separator();
                                     if ui.button("Save").clicked() {
@@ -267,118 +793,1658 @@ impl eframe::App for MyApp {
                     })
                 });
 
+                let mut stack_burst = None;
                 egui::SidePanel::left("photo-list").show(ctx, |ui| {
+                    ui.checkbox(&mut self.status.hide_poor_photos, "Hide poor shots");
                     ui.vertical(|ui| {
-                        for (index, photo) in photos.photos.iter().enumerate() {
-                            let size = photo.preview.size();
-                            let button = ImageButton::new(
-                                photo.preview.texture_id(ctx),
-                                [size[0] as f32, size[1] as f32],
-                            )
-                            .selected(index == *current_photo_index);
-                            if ui.add(button).clicked() {
-                                if *current_photo_index != index {
-                                    match RetainedImage::from_image_bytes(
-                                        "selected-photo",
-                                        &photos.photos[index].bytes,
-                                    ) {
-                                        Ok(new_photo) => {
-                                            *current_photo_index = index;
-                                            *current_photo = new_photo;
-                                        }
-                                        Err(error) => {
-                                            self.status.error =
-                                                Some(DScopeError::cannot_create_image(
-                                                    error,
-                                                    photo_file_name(index),
-                                                ))
-                                        }
-                                    }
+                        let visible = |index: usize| {
+                            let photo = &photos.photos[index];
+                            !(self.status.hide_poor_photos
+                                && (photo.info.flagged || photo.quality.is_poor())
+                                && index != *current_photo_index)
+                        };
+                        for burst in photos.bursts() {
+                            let representative = burst.representative;
+                            if visible(representative) {
+                                if photo_preview(
+                                    ui,
+                                    ctx,
+                                    &photos.photos[representative],
+                                    representative == *current_photo_index,
+                                ) {
+                                    select_index = Some(representative);
                                 }
+                                panorama_checkbox(ui, panorama, representative);
                             }
+                            if burst.photos.len() < 2 {
+                                continue;
+                            }
+                            let others: Vec<usize> = burst
+                                .photos
+                                .iter()
+                                .copied()
+                                .filter(|index| *index != representative && visible(*index))
+                                .collect();
+                            CollapsingHeader::new(format!("Burst of {}", burst.photos.len()))
+                                .id_source(("burst", photos.photos[burst.photos[0]].id))
+                                .show(ui, |ui| {
+                                    if ui.button("Focus stack").clicked() {
+                                        stack_burst = Some(burst.photos.clone());
+                                    }
+                                    for index in others {
+                                        if photo_preview(
+                                            ui,
+                                            ctx,
+                                            &photos.photos[index],
+                                            index == *current_photo_index,
+                                        ) {
+                                            select_index = Some(index);
+                                        }
+                                        panorama_checkbox(ui, panorama, index);
+                                    }
+                                });
                         }
                     });
                 });
 
+                if let Some(view) = overview {
+                    let mut close = false;
+                    egui::SidePanel::right("overview")
+                        .default_width(400.0)
+                        .show(ctx, |ui| {
+                            match overview_panel(ui, ctx, photos, *current_photo_index, view) {
+                                Ok(OverviewAction::Select(index)) => select_index = Some(index),
+                                Ok(OverviewAction::Save) => *save = true,
+                                Ok(OverviewAction::Close) => close = true,
+                                Ok(OverviewAction::None) => {}
+                                Err(error) => self.status.error = Some(error),
+                            }
+                        });
+                    if close {
+                        *overview = None;
+                    }
+                }
+
+                if let Some(burst) = stack_burst {
+                    match focus_stack_burst(photos, &burst) {
+                        Ok(index) => select_index = Some(index),
+                        Err(error) => self.status.error = Some(error),
+                    }
+                }
+
+                if let Some(index) = select_index {
+                    if index != *current_photo_index {
+                        if let Err(error) = select_photo(
+                            photos,
+                            index,
+                            self.status.remove_hair,
+                            current_photo_index,
+                            current_photo,
+                        ) {
+                            self.status.error = Some(error);
+                        }
+                    }
+                }
+
                 egui::CentralPanel::default().show(ctx, |ui| {
                     let unlock_movement = !*edit_measures;
+                    let px_per_mm = photos.info.calibration.px_per_mm;
+                    let current = &mut photos.photos[*current_photo_index];
+                    let previous_metrics = current.info.mole_metrics.clone();
+                    let current_metrics = &mut current.info.mole_metrics;
+                    let proposal = current
+                        .proposal
+                        .as_ref()
+                        .filter(|_| *edit_measures || current_metrics.size().is_none());
+
+                    match compare {
+                        Some(comparison) if comparison.mode == CompareMode::SideBySide => {
+                            ui.columns(2, |columns| {
+                                photo_plot("main-panel", Some(&comparison.link), unlock_movement)
+                                    .show(&mut columns[0], |plot| {
+                                        plot_photo(
+                                            plot,
+                                            ctx,
+                                            &current_photo.photo,
+                                            px_per_mm,
+                                            Color32::WHITE,
+                                        );
+                                        if self.status.show_glare {
+                                            plot_photo(
+                                                plot,
+                                                ctx,
+                                                &current_photo.glare,
+                                                px_per_mm,
+                                                Color32::WHITE,
+                                            );
+                                        }
+                                        if let Some(changes) = &comparison.changes {
+                                            plot_photo(
+                                                plot,
+                                                ctx,
+                                                changes,
+                                                px_per_mm,
+                                                Color32::WHITE,
+                                            );
+                                        }
+                                        if *show_measures {
+                                            plot_proposal(plot, proposal);
+                                            plot_measures(plot, current_metrics, Color32::WHITE);
+                                        }
+                                        if *edit_measures {
+                                            edit_measures_on_plot(plot, current_metrics);
+                                        }
+                                    });
+                                photo_plot(
+                                    "compare-panel",
+                                    Some(&comparison.link),
+                                    unlock_movement,
+                                )
+                                .show(&mut columns[1], |plot| {
+                                    plot_photo(
+                                        plot,
+                                        ctx,
+                                        &comparison.photo,
+                                        comparison.px_per_mm,
+                                        Color32::WHITE,
+                                    );
+                                    if *show_measures {
+                                        plot_measures(
+                                            plot,
+                                            &comparison.mole_metrics,
+                                            COMPARED_MEASURES_COLOR,
+                                        );
+                                    }
+                                });
+                            });
+                        }
+                        _ => {
+                            photo_plot("main-panel", None, unlock_movement).show(ui, |plot| {
+                                plot_photo(
+                                    plot,
+                                    ctx,
+                                    &current_photo.photo,
+                                    px_per_mm,
+                                    Color32::WHITE,
+                                );
+                                if self.status.show_glare {
+                                    plot_photo(
+                                        plot,
+                                        ctx,
+                                        &current_photo.glare,
+                                        px_per_mm,
+                                        Color32::WHITE,
+                                    );
+                                }
+                                if let Some(comparison) = compare {
+                                    let visible = if comparison.flicker {
+                                        ctx.request_repaint();
+                                        comparison.flicker_visible(ctx.input().time)
+                                    } else {
+                                        true
+                                    };
+                                    if visible {
+                                        let alpha = if comparison.flicker {
+                                            1.0
+                                        } else {
+                                            comparison.opacity
+                                        };
+                                        plot_photo(
+                                            plot,
+                                            ctx,
+                                            &comparison.photo,
+                                            comparison.px_per_mm,
+                                            Color32::from_white_alpha((alpha * 255.0) as u8),
+                                        );
+                                    }
+                                    if let Some(changes) = &comparison.changes {
+                                        plot_photo(plot, ctx, changes, px_per_mm, Color32::WHITE);
+                                    }
+                                    if *show_measures {
+                                        plot_measures(
+                                            plot,
+                                            &comparison.mole_metrics,
+                                            COMPARED_MEASURES_COLOR,
+                                        );
+                                    }
+                                }
+                                if *show_measures {
+                                    plot_proposal(plot, proposal);
+                                    plot_measures(plot, current_metrics, Color32::WHITE);
+                                }
+                                if *edit_measures {
+                                    edit_measures_on_plot(plot, current_metrics);
+                                }
+                            });
+                        }
+                    }
+                    if current.info.mole_metrics != previous_metrics {
+                        current.info.abcd = None;
+                    }
+                });
+            }
+        }
+    }
+}
 
-                    Plot::new("main-panel")
-                        .data_aspect(1.0)
-                        .allow_zoom(unlock_movement)
-                        .allow_scroll(false)
-                        .allow_drag(unlock_movement)
-                        .show_axes([false, false])
-                        .show(ui, |plot| {
-                            let size = current_photo.size();
-                            let image = PlotImage::new(
-                                current_photo.texture_id(ctx),
-                                Value::new(0.0, 0.0),
-                                [
-                                    size[0] as f32 / PHOTO_PX_PER_MM,
-                                    size[1] as f32 / PHOTO_PX_PER_MM,
-                                ],
+fn photo_preview(ui: &mut egui::Ui, ctx: &egui::Context, photo: &Photo, selected: bool) -> bool {
+    let size = photo.preview.size();
+    let button = ImageButton::new(
+        photo.preview.texture_id(ctx),
+        [size[0] as f32, size[1] as f32],
+    )
+    .selected(selected);
+    let clicked = ui.add(button).clicked();
+    quality_badge(ui, photo);
+    if let Some(derivation) = &photo.info.derived_from {
+        ui.label(derivation.to_string());
+    }
+    clicked
+}
+
+fn quality_badge(ui: &mut egui::Ui, photo: &Photo) {
+    let quality = &photo.quality;
+    let issues = quality.issues();
+    let response = if photo.info.flagged {
+        ui.colored_label(Color32::RED, "Poor shot")
+    } else if issues.is_empty() {
+        ui.colored_label(Color32::LIGHT_GREEN, "Good")
+    } else {
+        ui.colored_label(Color32::GOLD, issues.join(", "))
+    };
+    response.on_hover_text(format!(
+        "Sharpness {:.0}, clipped {:.1}%, glare {:.1}%",
+        quality.sharpness, quality.clipped, quality.glare
+    ));
+}
+
+fn photo_plot(id: &str, link: Option<&LinkedAxisGroup>, unlock_movement: bool) -> Plot {
+    let plot = Plot::new(id)
+        .data_aspect(1.0)
+        .allow_zoom(unlock_movement)
+        .allow_scroll(false)
+        .allow_drag(unlock_movement)
+        .show_axes([false, false]);
+    match link {
+        Some(link) => plot.link_axis(link.clone()),
+        None => plot,
+    }
+}
+
+fn plot_photo(
+    plot: &mut PlotUi,
+    ctx: &egui::Context,
+    photo: &RetainedImage,
+    px_per_mm: f32,
+    tint: Color32,
+) {
+    let size = photo.size();
+    let image = PlotImage::new(
+        photo.texture_id(ctx),
+        Value::new(0.0, 0.0),
+        [size[0] as f32 / px_per_mm, size[1] as f32 / px_per_mm],
+    )
+    .tint(tint);
+    plot.image(image);
+}
+
+fn plot_outline(plot: &mut PlotUi, points: &[Point], stroke: Stroke) {
+    let values = points
+        .iter()
+        .chain(points.first())
+        .map(|point| Value::new(point[0], point[1]))
+        .collect();
+    plot.line(Line::new(Values::from_values(values)).stroke(stroke));
+}
+
+fn plot_measures(plot: &mut PlotUi, metrics: &MoleMetrics, color: Color32) {
+    plot_outline(plot, &metrics.outline_points(64), Stroke::new(3.0, color));
+}
+
+fn plot_proposal(plot: &mut PlotUi, proposal: Option<&MoleMetrics>) {
+    if let Some(proposal) = proposal {
+        let values = proposal
+            .outline_points(64)
+            .iter()
+            .chain(proposal.outline_points(64).first())
+            .map(|point| Value::new(point[0], point[1]))
+            .collect();
+        plot.line(
+            Line::new(Values::from_values(values))
+                .stroke(Stroke::new(2.0, PROPOSAL_COLOR))
+                .style(LineStyle::dashed_loose()),
+        );
+    }
+}
+
+/// The sliders keep the mole within `photo_size` millimetres.
+fn edit_measures_panel(
+    ui: &mut egui::Ui,
+    metrics: &mut MoleMetrics,
+    proposal: Option<&MoleMetrics>,
+    photo_size: [f32; 2],
+) {
+    if ui
+        .add_enabled(proposal.is_some(), Button::new("Accept proposal"))
+        .on_hover_text("Use the outline found by the automatic segmentation")
+        .clicked()
+    {
+        if let Some(proposal) = proposal {
+            *metrics = proposal.clone();
+        }
+    }
+    ui.separator();
+
+    ui.label("Shape");
+    let mut shape_name = metrics.shape.name();
+    ComboBox::from_id_source("measure-shape")
+        .selected_text(shape_name)
+        .show_ui(ui, |ui| {
+            for name in ["Circle", "Ellipse", "Outline"] {
+                ui.selectable_value(&mut shape_name, name, name);
+            }
+        });
+    if shape_name != metrics.shape.name() {
+        metrics.switch_shape(shape_name);
+    }
+    ui.separator();
+
+    let [width, height] = photo_size;
+    let center_slider = |ui: &mut egui::Ui, label: &str, value: &mut f32, extent: f32| {
+        ui.label(label);
+        ui.add(Slider::new(value, -extent / 2.0..=extent / 2.0).clamp_to_range(true));
+    };
+    let size_slider = |ui: &mut egui::Ui, label: &str, value: &mut f32| {
+        ui.label(label);
+        ui.add(Slider::new(value, 0.0..=width.max(height)).clamp_to_range(true));
+    };
+    match &mut metrics.shape {
+        MoleShape::Circle => {
+            center_slider(ui, "X", &mut metrics.center_x, width);
+            center_slider(ui, "Y", &mut metrics.center_y, height);
+            size_slider(ui, "Size", &mut metrics.diameter);
+        }
+        MoleShape::Ellipse {
+            major_axis,
+            minor_axis,
+            rotation,
+        } => {
+            center_slider(ui, "X", &mut metrics.center_x, width);
+            center_slider(ui, "Y", &mut metrics.center_y, height);
+            size_slider(ui, "Major", major_axis);
+            size_slider(ui, "Minor", minor_axis);
+            ui.label("Rotation");
+            ui.add(Slider::new(rotation, -PI / 2.0..=PI / 2.0).clamp_to_range(true));
+            if *minor_axis > *major_axis {
+                std::mem::swap(minor_axis, major_axis);
+                *rotation += if *rotation > 0.0 { -PI / 2.0 } else { PI / 2.0 };
+            }
+        }
+        MoleShape::Outline { points, spline } => {
+            ui.label(format!("{} points", points.len()));
+            ui.checkbox(spline, "Spline");
+            if ui
+                .add_enabled(!points.is_empty(), Button::new("Undo point"))
+                .clicked()
+            {
+                points.pop();
+            }
+            if ui
+                .add_enabled(!points.is_empty(), Button::new("Clear"))
+                .clicked()
+            {
+                points.clear();
+            }
+        }
+    }
+    metrics.update_measures();
+
+    if let Some(measures) = metrics.measures {
+        ui.separator();
+        ui.label(format!(
+            "area {:.2} mm², perimeter {:.2} mm, axes {:.2} x {:.2} mm",
+            measures.area, measures.perimeter, measures.major_axis, measures.minor_axis
+        ));
+    }
+}
+
+fn edit_calibration_panel(
+    ui: &mut egui::Ui,
+    photos: &mut PhotoSet,
+    calibrations: &mut CalibrationLibrary,
+    new_calibration: &mut Option<CalibrationProfile>,
+    error: &mut Option<DScopeError>,
+) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Calibration");
+        let mut selected = None;
+        ComboBox::from_id_source("calibration-profile")
+            .selected_text(&photos.info.calibration.name)
+            .show_ui(ui, |ui| {
+                for profile in calibrations.profiles.iter() {
+                    if ui
+                        .selectable_label(profile == &photos.info.calibration, &profile.name)
+                        .clicked()
+                    {
+                        selected = Some(profile.clone());
+                    }
+                }
+            });
+        if let Some(profile) = selected {
+            photos.set_calibration(profile);
+            changed = true;
+        }
+        ui.label(format!("{} px/mm", photos.info.calibration.px_per_mm));
+
+        let mut flat_field_changed = false;
+        if ui
+            .button("Flat field")
+            .on_hover_text("Correct vignetting with a photo of a uniform white reference")
+            .clicked()
+        {
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter("Images", &["jpg", "jpeg", "png"])
+                .pick_file()
+            {
+                match load_calibration_photo(&path) {
+                    Ok((_, reference)) => {
+                        photos.info.calibration.flat_field =
+                            Some(FlatField::from_reference(&reference));
+                        flat_field_changed = true;
+                    }
+                    Err(load_error) => *error = Some(load_error),
+                }
+            }
+        }
+        if ui
+            .add_enabled(
+                photos.info.calibration.flat_field.is_some(),
+                Button::new("Clear flat field"),
+            )
+            .clicked()
+        {
+            photos.info.calibration.flat_field = None;
+            flat_field_changed = true;
+        }
+        if flat_field_changed {
+            photos.clear_abcd_scores();
+            calibrations.add(photos.info.calibration.clone());
+            if let Err(save_error) = calibrations.save() {
+                *error = Some(save_error);
+            }
+            changed = true;
+        }
+
+        ui.separator();
+        match new_calibration {
+            Some(profile) => {
+                ui.label("Name");
+                ui.text_edit_singleline(&mut profile.name);
+                ui.add(
+                    egui::DragValue::new(&mut profile.px_per_mm)
+                        .clamp_range(1.0..=10000.0)
+                        .suffix(" px/mm"),
+                );
+                if ui
+                    .add_enabled(!profile.name.is_empty(), Button::new("Add"))
+                    .clicked()
+                {
+                    let profile = new_calibration.take().unwrap();
+                    calibrations.add(profile.clone());
+                    if let Err(save_error) = calibrations.save() {
+                        *error = Some(save_error);
+                    }
+                    photos.set_calibration(profile);
+                    changed = true;
+                } else if ui.button("Cancel").clicked() {
+                    *new_calibration = None;
+                }
+            }
+            None => {
+                if ui.button("New profile").clicked() {
+                    *new_calibration = Some(CalibrationProfile {
+                        name: String::new(),
+                        ..photos.info.calibration.clone()
+                    });
+                }
+            }
+        }
+    });
+    changed
+}
+
+fn edit_findings_panel(ui: &mut egui::Ui, findings: &mut SevenPointChecklist) {
+    ui.vertical(|ui| {
+        ui.label("7-point checklist");
+        for (name, weight, present) in findings.criteria_mut() {
+            ui.checkbox(present, format!("{} ({})", name, weight));
+        }
+        let score = findings.score();
+        if findings.is_suspicious() {
+            ui.colored_label(Color32::RED, format!("score {}: suspicious", score));
+        } else {
+            ui.label(format!("score {}", score));
+        }
+    });
+}
+
+fn edit_abcd_panel(
+    ui: &mut egui::Ui,
+    photo: &mut Photo,
+    visit: &PhotoSetInfo,
+    error: &mut Option<DScopeError>,
+) {
+    ui.vertical(|ui| {
+        ui.label("ABCD");
+        let structures = photo.info.abcd.map(|abcd| abcd.structures).unwrap_or(0);
+        if ui
+            .add_enabled(
+                photo.info.mole_metrics.size().is_some(),
+                Button::new("Compute"),
+            )
+            .on_hover_text("Score asymmetry, border and colours inside the outline")
+            .clicked()
+        {
+            match photo.corrected(&visit.calibration, visit.colour_matrix.as_ref()) {
+                Ok(image) => {
+                    photo.info.abcd = compute_abcd(
+                        &DynamicImage::ImageRgb8(image),
+                        &photo.info.mole_metrics,
+                        visit.calibration.px_per_mm,
+                        structures,
+                    )
+                }
+                Err(decode_error) => *error = Some(decode_error),
+            }
+        }
+        if let Some(abcd) = photo.info.abcd.as_mut() {
+            ui.add(
+                Slider::new(&mut abcd.structures, 0..=STRUCTURES_MAX)
+                    .text("Structures")
+                    .clamp_to_range(true),
+            )
+            .on_hover_text("Network, structureless areas, branched streaks, dots and globules");
+            ui.label(format!("{}: {}", abcd, abcd.assessment()));
+        }
+    });
+}
+
+fn edit_measures_on_plot(plot: &mut PlotUi, metrics: &mut MoleMetrics) {
+    if let MoleShape::Outline { points, .. } = &mut metrics.shape {
+        if plot.plot_clicked() {
+            if let Some(point) = plot.pointer_coordinate() {
+                points.push([point.x as f32, point.y as f32]);
+            }
+        }
+        plot.points(
+            Points::new(Values::from_values(
+                points
+                    .iter()
+                    .map(|point| Value::new(point[0], point[1]))
+                    .collect(),
+            ))
+            .radius(3.0)
+            .color(Color32::WHITE),
+        );
+        metrics.update_measures();
+        return;
+    }
+
+    if let Some(point) = plot.pointer_coordinate() {
+        let px = point.x as f32;
+        let py = point.y as f32;
+
+        if plot.plot_clicked() {
+            metrics.center_x = px;
+            metrics.center_y = py;
+        }
+
+        let drag = plot.pointer_coordinate_drag_delta();
+        if drag[0] != 0.0 || drag[1] != 0.0 {
+            let p2_x = px;
+            let p2_y = py;
+            let p1_x = px - drag[0];
+            let p1_y = py - drag[1];
+            let cx = metrics.center_x;
+            let cy = metrics.center_y;
+
+            let r2 = ((p2_x - cx).powf(2.0) + (p2_y - cy).powf(2.0)).sqrt();
+            let r1 = ((p1_x - cx).powf(2.0) + (p1_y - cy).powf(2.0)).sqrt();
+
+            match &mut metrics.shape {
+                MoleShape::Ellipse { major_axis, .. } => *major_axis += 2.0 * (r2 - r1),
+                _ => metrics.diameter += 2.0 * (r2 - r1),
+            }
+        }
+        metrics.update_measures();
+    }
+}
+
+fn photo_texture(
+    photos: &PhotoSet,
+    index: usize,
+    remove_hair: bool,
+) -> Result<PhotoView, DScopeError> {
+    let mut image = photos.photos[index]
+        .corrected(&photos.info.calibration, photos.info.colour_matrix.as_ref())?;
+    if remove_hair {
+        image = hair::remove_hair(&image);
+    }
+    Ok(PhotoView {
+        photo: retained_image("selected-photo", &image),
+        glare: glare_overlay(&image),
+    })
+}
+
+fn glare_overlay(image: &RgbImage) -> RetainedImage {
+    let mask = glare::glare_mask(image);
+    let (width, height) = image.dimensions();
+    let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            if mask.get(x, y) {
+                rgba.extend_from_slice(&GLARE_OVERLAY_COLOR);
+            } else {
+                rgba.extend_from_slice(&[0, 0, 0, 0]);
+            }
+        }
+    }
+    RetainedImage::from_color_image(
+        "glare-overlay",
+        ColorImage::from_rgba_unmultiplied([width as usize, height as usize], &rgba),
+    )
+}
+
+enum OverviewAction {
+    None,
+    Select(usize),
+    Save,
+    Close,
+}
+
+fn overview_panel(
+    ui: &mut egui::Ui,
+    ctx: &egui::Context,
+    photos: &mut PhotoSet,
+    current_photo_index: usize,
+    view: &mut OverviewView,
+) -> Result<OverviewAction, DScopeError> {
+    let mut action = OverviewAction::None;
+    let photo_id = photos.photos[current_photo_index].id;
+    let mut index = view.index;
+    ui.horizontal(|ui| {
+        ComboBox::from_id_source("overview-photo")
+            .selected_text(&photos.overviews[index].file_name)
+            .show_ui(ui, |ui| {
+                for (other, overview) in photos.overviews.iter().enumerate() {
+                    ui.selectable_value(&mut index, other, &overview.file_name);
+                }
+            });
+        if ui.button("Close").clicked() {
+            action = OverviewAction::Close;
+        }
+    });
+    if index != view.index {
+        view.image = photos.overviews[index].image()?;
+        view.index = index;
+    }
+
+    let info = &mut photos.overviews[index].info;
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut view.mark, "Mark")
+            .on_hover_text("Click the lesion of the current photo to link them");
+        if ui
+            .add_enabled(info.is_linked(photo_id), Button::new("Unlink"))
+            .clicked()
+        {
+            info.unlink(photo_id);
+        }
+        if ui.button("Save").clicked() {
+            action = OverviewAction::Save;
+        }
+    });
+
+    let mut clicked = None;
+    photo_plot("overview-plot", None, true).show(ui, |plot| {
+        plot_photo(
+            plot,
+            ctx,
+            &view.image,
+            view.image.size()[0] as f32,
+            Color32::WHITE,
+        );
+        for marker in info.markers.iter() {
+            let color = if marker.photo_ids.contains(&photo_id) {
+                Color32::YELLOW
+            } else {
+                Color32::RED
+            };
+            let position = Value::new(marker.position[0], marker.position[1]);
+            plot.points(
+                Points::new(Values::from_values(vec![position]))
+                    .radius(5.0)
+                    .color(color),
+            );
+            let ids: Vec<String> = marker.photo_ids.iter().map(|id| id.to_string()).collect();
+            plot.text(
+                Text::new(position, format!("  [{}]", ids.join(", ")))
+                    .anchor(Align2::LEFT_CENTER)
+                    .color(color),
+            );
+        }
+        if plot.plot_clicked() {
+            clicked = plot
+                .pointer_coordinate()
+                .map(|point| [point.x as f32, point.y as f32]);
+        }
+    });
+
+    if let Some(point) = clicked {
+        if view.mark {
+            info.mark(point, photo_id);
+        } else if let Some(marker) = info.marker_at(point) {
+            let ids = &info.markers[marker].photo_ids;
+            let next = match ids.iter().position(|id| *id == photo_id) {
+                Some(position) => ids[(position + 1) % ids.len()],
+                None => ids[0],
+            };
+            if let Some(index) = photos.photo_index(next) {
+                action = OverviewAction::Select(index);
+            }
+        }
+    }
+    Ok(action)
+}
+
+fn focus_stack_burst(photos: &mut PhotoSet, burst: &[usize]) -> Result<usize, DScopeError> {
+    let images = burst
+        .iter()
+        .map(|index| Ok(photos.photos[*index].decode()?.to_rgb8()))
+        .collect::<Result<Vec<_>, DScopeError>>()?;
+    let stacked = focus_stack(&images, photos.info.calibration.px_per_mm).ok_or_else(|| {
+        DScopeError::cannot_create_image("no photo to stack".to_string(), "focus stack".to_string())
+    })?;
+    let sources = burst.iter().map(|index| photos.photos[*index].id).collect();
+    photos.add_derived_photo(&stacked, Derivation::FocusStack { sources })
+}
+
+fn stitch_panorama(photos: &mut PhotoSet, selection: &[usize]) -> Result<usize, DScopeError> {
+    let images = selection
+        .iter()
+        .map(|index| {
+            photos.photos[*index]
+                .corrected(&photos.info.calibration, photos.info.colour_matrix.as_ref())
+        })
+        .collect::<Result<Vec<_>, DScopeError>>()?;
+    let mosaic = stitch(&images, photos.info.calibration.px_per_mm).map_err(|position| {
+        DScopeError::cannot_register_photo(photo_file_name(photos.photos[selection[position]].id))
+    })?;
+    let sources = selection
+        .iter()
+        .map(|index| photos.photos[*index].id)
+        .collect();
+    photos.add_derived_photo(&mosaic, Derivation::Panorama { sources })
+}
+
+fn panorama_checkbox(ui: &mut egui::Ui, panorama: &mut Option<Vec<usize>>, index: usize) {
+    if let Some(selection) = panorama {
+        let mut selected = selection.contains(&index);
+        if ui.checkbox(&mut selected, "Stitch").changed() {
+            if selected {
+                selection.push(index);
+            } else {
+                selection.retain(|selected| *selected != index);
+            }
+        }
+    }
+}
+
+fn select_photo(
+    photos: &PhotoSet,
+    index: usize,
+    remove_hair: bool,
+    current_photo_index: &mut usize,
+    current_photo: &mut PhotoView,
+) -> Result<(), DScopeError> {
+    let new_photo = photo_texture(photos, index, remove_hair)?;
+    *current_photo_index = index;
+    *current_photo = new_photo;
+    Ok(())
+}
+
+/// Opens the archive containing the visit if the patient is not in the current one.
+fn select_visit_patient(status: &mut DScopeStatus, visit_path: &Path, patient_id: &str) {
+    let known = status
+        .archive
+        .as_ref()
+        .and_then(|archive| archive.patient_index(patient_id))
+        .is_some();
+    if !known {
+        if let Some(archive_path) = visit_path.parent().and_then(|path| path.parent()) {
+            if let Ok(archive) = Archive::from_path(archive_path.to_path_buf()) {
+                if archive.patient_index(patient_id).is_some() {
+                    status.archive = Some(archive);
+                }
+            }
+        }
+    }
+    status.current_patient_index = status
+        .archive
+        .as_ref()
+        .and_then(|archive| archive.patient_index(patient_id));
+}
+
+fn visit_patient<'a>(
+    archive: &'a mut Option<Archive>,
+    current_patient_index: Option<usize>,
+    photos: &PhotoSet,
+) -> Option<&'a mut Patient> {
+    let patient = archive
+        .as_mut()
+        .zip(current_patient_index)
+        .map(|(archive, index)| &mut archive.patients[index])?;
+    if photos.info.patient_id.as_ref() == Some(&patient.info.id) {
+        Some(patient)
+    } else {
+        None
+    }
+}
+
+fn edit_photo_mole(
+    ui: &mut egui::Ui,
+    patient: &mut Patient,
+    mole_id: &mut Option<MoleId>,
+    new_mole: &mut Option<NewMole>,
+    error: &mut Option<DScopeError>,
+) {
+    ui.horizontal(|ui| {
+        ui.label("Mole");
+        let selected_text = mole_id
+            .and_then(|id| patient.info.mole(id))
+            .map(|mole| mole.display_name())
+            .unwrap_or_else(|| "None".to_string());
+        ComboBox::from_id_source("photo-mole")
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(mole_id, None, "None");
+                for mole in patient.info.moles.iter() {
+                    ui.selectable_value(mole_id, Some(mole.id), mole.display_name());
+                }
+            });
+        if let Some(mole) = mole_id.and_then(|id| patient.info.mole(id)) {
+            ui.label(&mole.location);
+        }
+
+        ui.separator();
+        match new_mole {
+            Some(mole) => {
+                ui.label("Label");
+                ui.text_edit_singleline(&mut mole.label);
+                ui.label("Location");
+                ui.text_edit_singleline(&mut mole.location);
+                if ui.button("Add mole").clicked() {
+                    let mole = new_mole.take().unwrap();
+                    let id = patient.info.add_mole(mole.label, mole.location);
+                    match patient.save() {
+                        Ok(()) => *mole_id = Some(id),
+                        Err(save_error) => *error = Some(save_error),
+                    }
+                } else if ui.button("Cancel").clicked() {
+                    *new_mole = None;
+                }
+            }
+            None => {
+                if ui.button("New mole").clicked() {
+                    *new_mole = Some(NewMole {
+                        label: String::new(),
+                        location: String::new(),
+                    });
+                }
+            }
+        }
+    });
+}
+
+fn mole_history_window(ctx: &egui::Context, status: &mut DScopeStatus) {
+    let history = match &status.mole_history {
+        Some(history) => history,
+        None => return,
+    };
+    let title = status
+        .archive
+        .as_ref()
+        .zip(status.current_patient_index)
+        .and_then(|(archive, index)| archive.patients[index].info.mole(history.mole_id))
+        .map(|mole| format!("Mole {}", mole.display_name()))
+        .unwrap_or_else(|| format!("Mole #{}", history.mole_id));
+
+    let measure = &mut status.timeline_measure;
+    let growth_alert = &mut status.growth_alert;
+    // Growth alerts always follow the diameter, whatever measure is charted.
+    let diameter_points = timeline_points(&history.observations, |observation| {
+        TimelineMeasure::Diameter.value(&observation.info.mole_metrics)
+    });
+    let diameter_rate = |observation_index: usize| {
+        diameter_points
+            .iter()
+            .find(|point| point.observation_index == observation_index)
+            .and_then(|point| point.rate)
+    };
+
+    let mut open = true;
+    let mut selected = None;
+    egui::Window::new(title)
+        .id(egui::Id::new("mole-history"))
+        .open(&mut open)
+        .show(ctx, |ui| {
+            if history.observations.is_empty() {
+                ui.label("No saved photos of this mole");
+                return;
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Growth alert");
+                ui.add(
+                    Slider::new(growth_alert, 0.0..=GROWTH_ALERT_MAX)
+                        .suffix(" mm/month")
+                        .clamp_to_range(true),
+                );
+            });
+            let growth_alert = *growth_alert;
+            let is_alert =
+                |rate: Option<f32>| rate.map(|rate| rate > growth_alert).unwrap_or(false);
+
+            ui.horizontal(|ui| {
+                ui.radio_value(measure, TimelineMeasure::Diameter, "Diameter");
+                ui.radio_value(measure, TimelineMeasure::Area, "Area");
+            });
+            let points = timeline_points(&history.observations, |observation| {
+                measure.value(&observation.info.mole_metrics)
+            });
+
+            ui.label(format!(
+                "{}, months since {}",
+                measure.label(),
+                DisplayTime::new(history.observations[0].info.time)
+            ));
+            Plot::new("mole-timeline")
+                .height(200.0)
+                .allow_scroll(false)
+                .include_y(0.0)
+                .show(ui, |plot| {
+                    for pair in points.windows(2) {
+                        let color = if is_alert(diameter_rate(pair[1].observation_index)) {
+                            Color32::RED
+                        } else {
+                            Color32::LIGHT_BLUE
+                        };
+                        plot.line(
+                            Line::new(Values::from_values(
+                                pair.iter()
+                                    .map(|point| Value::new(point.months, point.value))
+                                    .collect(),
+                            ))
+                            .stroke(Stroke::new(2.0, color)),
+                        );
+                    }
+                    plot.points(
+                        Points::new(Values::from_values(
+                            points
+                                .iter()
+                                .map(|point| Value::new(point.months, point.value))
+                                .collect(),
+                        ))
+                        .radius(4.0)
+                        .color(Color32::WHITE),
+                    );
+                });
+
+            egui::Grid::new("mole-history-grid")
+                .striped(true)
+                .show(ui, |ui| {
+                    for (index, observation) in history.observations.iter().enumerate() {
+                        ui.label(format!("{}", DisplayTime::new(observation.info.time)));
+                        ui.label(format!("[{}]", observation.photo_id));
+                        match observation.info.mole_metrics.size() {
+                            Some(size) => ui.label(format!("{} mm", size)),
+                            None => ui.label("-"),
+                        };
+                        match observation.info.mole_metrics.area() {
+                            Some(area) => ui.label(format!("{:.2} mm²", area)),
+                            None => ui.label("-"),
+                        };
+                        match diameter_rate(index) {
+                            Some(rate) if is_alert(Some(rate)) => {
+                                ui.colored_label(Color32::RED, format!("{:+.2} mm/month", rate))
+                            }
+                            Some(rate) => ui.label(format!("{:+.2} mm/month", rate)),
+                            None => ui.label(""),
+                        };
+                        if ui.button("Open").clicked() {
+                            selected = Some(observation.clone());
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+
+    if let Some(observation) = selected {
+        open_observation(status, observation);
+    }
+    if !open {
+        status.mole_history = None;
+    }
+}
+
+fn open_observation(status: &mut DScopeStatus, observation: MoleObservation) {
+    let current_path = match &status.ui {
+        DScopeUi::Show { photos, .. } => Some(&photos.path),
+        DScopeUi::Empty => None,
+    };
+    if current_path == Some(&observation.visit_path) {
+        status.select_photo_id = Some(observation.photo_id);
+    } else {
+        status.load = Some(observation.visit_path);
+        status.load_photo_id = Some(observation.photo_id);
+    }
+}
+
+fn body_map_window(ctx: &egui::Context, status: &mut DScopeStatus) {
+    let body_map = match &mut status.body_map {
+        Some(body_map) => body_map,
+        None => return,
+    };
+    let patient = match status
+        .archive
+        .as_mut()
+        .zip(status.current_patient_index)
+        .and_then(|(archive, index)| archive.patients.get_mut(index))
+    {
+        Some(patient) => patient,
+        None => {
+            status.body_map = None;
+            return;
+        }
+    };
+
+    let mut open = true;
+    let mut clicked = None;
+    egui::Window::new(format!("Body map of {}", patient.info.display_name()))
+        .id(egui::Id::new("body-map"))
+        .open(&mut open)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for view in BodyView::ALL {
+                    ui.radio_value(&mut body_map.view, view, view.label());
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Place");
+                let selected_text = body_map
+                    .place_mole_id
+                    .and_then(|id| patient.info.mole(id))
+                    .map(|mole| mole.display_name())
+                    .unwrap_or_else(|| "None".to_string());
+                ComboBox::from_id_source("body-map-mole")
+                    .selected_text(selected_text)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut body_map.place_mole_id, None, "None");
+                        for mole in patient.info.moles.iter() {
+                            ui.selectable_value(
+                                &mut body_map.place_mole_id,
+                                Some(mole.id),
+                                mole.display_name(),
                             );
-                            plot.image(image);
+                        }
+                    });
+            });
 
-                            if *show_measures {
-                                let current_photo_info = &photos.photos[*current_photo_index];
-                                plot.line(
-                                    Line::new(Values::from_values_iter(circle(
-                                        current_photo_info.info.mole_metrics.center_x,
-                                        current_photo_info.info.mole_metrics.center_y,
-                                        current_photo_info.info.mole_metrics.diameter / 2.0,
-                                        64,
-                                    )))
-                                    .stroke(Stroke::new(3.0, Color32::WHITE)),
-                                );
+            let view = body_map.view;
+            Plot::new("body-map")
+                .data_aspect(1.0)
+                .height(400.0)
+                .include_x(-1.0)
+                .include_x(1.0)
+                .include_y(-1.0)
+                .include_y(1.0)
+                .allow_drag(false)
+                .allow_zoom(false)
+                .allow_scroll(false)
+                .allow_boxed_zoom(false)
+                .show_axes([false, false])
+                .show(ui, |plot| {
+                    for outline in view.silhouette() {
+                        plot.line(
+                            Line::new(Values::from_values(
+                                outline
+                                    .iter()
+                                    .chain(outline.first())
+                                    .map(|point| Value::new(point[0], point[1]))
+                                    .collect(),
+                            ))
+                            .color(Color32::GRAY),
+                        );
+                    }
+                    for mole in patient.info.moles.iter() {
+                        let marker = match mole.marker.filter(|marker| marker.view == view) {
+                            Some(marker) => marker,
+                            None => continue,
+                        };
+                        let color = if body_map.place_mole_id == Some(mole.id) {
+                            Color32::YELLOW
+                        } else {
+                            Color32::RED
+                        };
+                        let position = Value::new(marker.position[0], marker.position[1]);
+                        plot.points(
+                            Points::new(Values::from_values(vec![position]))
+                                .radius(5.0)
+                                .color(color),
+                        );
+                        plot.text(
+                            Text::new(position, format!("  {}", mole.display_name()))
+                                .anchor(Align2::LEFT_CENTER)
+                                .color(color),
+                        );
+                    }
+                    if plot.plot_clicked() {
+                        clicked = plot
+                            .pointer_coordinate()
+                            .map(|point| [point.x as f32, point.y as f32]);
+                    }
+                });
+        });
+
+    if let Some(point) = clicked {
+        let view = body_map.view;
+        match marker_at(&patient.info.moles, view, point) {
+            Some(mole_id) => {
+                let mut observations = patient.mole_history(mole_id);
+                match observations.pop() {
+                    Some(latest) => open_observation(status, latest),
+                    None => {
+                        status.mole_history = Some(MoleHistory {
+                            mole_id,
+                            observations,
+                        })
+                    }
+                }
+            }
+            None => {
+                let mole = body_map
+                    .place_mole_id
+                    .and_then(|id| patient.info.moles.iter_mut().find(|mole| mole.id == id));
+                if let Some(mole) = mole {
+                    mole.marker = Some(BodyMarker {
+                        view,
+                        position: point,
+                    });
+                    if let Err(error) = patient.save() {
+                        status.error = Some(error);
+                    }
+                }
+            }
+        }
+    }
+    if !open {
+        status.body_map = None;
+    }
+}
+
+fn load_calibration_photo(path: &Path) -> Result<(RetainedImage, DynamicImage), DScopeError> {
+    let file = path.to_string_lossy().to_string();
+    let bytes =
+        std::fs::read(path).map_err(|error| DScopeError::cannot_read_file(error, file.clone()))?;
+    let image = image::load_from_memory(&bytes)
+        .map_err(|error| DScopeError::cannot_decode_image(error, file.clone()))?;
+    let photo = RetainedImage::from_image_bytes("calibration-photo", &bytes)
+        .map_err(|error| DScopeError::cannot_create_image(error, file))?;
+    Ok((photo, image))
+}
+
+fn calibration_window(ctx: &egui::Context, status: &mut DScopeStatus) {
+    let wizard = match &mut status.calibration_wizard {
+        Some(wizard) => wizard,
+        None => return,
+    };
+
+    let mut open = true;
+    let mut save = false;
+    let mut use_for_visit = false;
+    let fit = fit_calibration(&wizard.marks, wizard.target, wizard.spacing);
+    egui::Window::new("Calibration")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Load target photo").clicked() {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Images", &["jpg", "jpeg", "png"])
+                        .pick_file()
+                    {
+                        match load_calibration_photo(&path) {
+                            Ok(photo) => {
+                                wizard.photo = Some(photo);
+                                wizard.marks.clear();
                             }
+                            Err(error) => status.error = Some(error),
+                        }
+                    }
+                }
+                ui.separator();
+                ui.radio_value(&mut wizard.target, CalibrationTarget::Ruler, "Ruler");
+                ui.radio_value(&mut wizard.target, CalibrationTarget::DotGrid, "Dot grid");
+                ui.add(
+                    egui::DragValue::new(&mut wizard.spacing)
+                        .clamp_range(0.1..=10.0)
+                        .speed(0.1)
+                        .suffix(" mm"),
+                );
+            });
 
-                            if *edit_measures {
-                                let current_photo_info = &mut photos.photos[*current_photo_index];
+            let (photo, image) = match &wizard.photo {
+                Some(photo) => photo,
+                None => {
+                    ui.label("Load a photo of a millimetre ruler or of a printed dot grid");
+                    return;
+                }
+            };
 
-                                if let Some(point) = plot.pointer_coordinate() {
-                                    let px = point.x as f32;
-                                    let py = point.y as f32;
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        wizard.target == CalibrationTarget::DotGrid,
+                        Button::new("Detect dots"),
+                    )
+                    .clicked()
+                {
+                    wizard.marks = detect_dots(image);
+                }
+                if ui
+                    .add_enabled(!wizard.marks.is_empty(), Button::new("Undo mark"))
+                    .clicked()
+                {
+                    wizard.marks.pop();
+                }
+                if ui
+                    .add_enabled(!wizard.marks.is_empty(), Button::new("Clear"))
+                    .clicked()
+                {
+                    wizard.marks.clear();
+                }
+                ui.label(format!(
+                    "{} marks, click the photo to add more",
+                    wizard.marks.len()
+                ));
+            });
 
-                                    if plot.plot_clicked() {
-                                        current_photo_info.info.mole_metrics.center_x = px;
-                                        current_photo_info.info.mole_metrics.center_y = py;
-                                    }
+            Plot::new("calibration-plot")
+                .data_aspect(1.0)
+                .height(400.0)
+                .allow_scroll(false)
+                .show_axes([false, false])
+                .show(ui, |plot| {
+                    plot_photo(plot, ctx, photo, 1.0, Color32::WHITE);
+                    if plot.plot_clicked() {
+                        if let Some(point) = plot.pointer_coordinate() {
+                            wizard.marks.push([point.x as f32, point.y as f32]);
+                        }
+                    }
+                    let values = |points: &[[f32; 2]]| {
+                        Values::from_values(
+                            points
+                                .iter()
+                                .map(|point| Value::new(point[0], point[1]))
+                                .collect(),
+                        )
+                    };
+                    plot.points(
+                        Points::new(values(&wizard.marks))
+                            .radius(4.0)
+                            .color(Color32::WHITE),
+                    );
+                    if let Some(fit) = &fit {
+                        plot.points(
+                            Points::new(values(&fit.predicted))
+                                .radius(2.0)
+                                .color(PROPOSAL_COLOR),
+                        );
+                    }
+                });
 
-                                    let drag = plot.pointer_coordinate_drag_delta();
-                                    if drag[0] != 0.0 || drag[1] != 0.0 {
-                                        let p2_x = px;
-                                        let p2_y = py;
-                                        let p1_x = px - drag[0];
-                                        let p1_y = py - drag[1];
-                                        let cx = current_photo_info.info.mole_metrics.center_x;
-                                        let cy = current_photo_info.info.mole_metrics.center_y;
-
-                                        let r2 =
-                                            ((p2_x - cx).powf(2.0) + (p2_y - cy).powf(2.0)).sqrt();
-                                        let r1 =
-                                            ((p1_x - cx).powf(2.0) + (p1_y - cy).powf(2.0)).sqrt();
-
-                                        current_photo_info.info.mole_metrics.diameter +=
-                                            2.0 * (r2 - r1);
-                                    }
+            match &fit {
+                Some(fit) => {
+                    ui.label(format!(
+                        "{:.1} px/mm, k1 {:.5}, residual {:.2} px",
+                        fit.profile.px_per_mm, fit.profile.k1, fit.residual
+                    ));
+                    ui.horizontal(|ui| {
+                        ui.label("Name");
+                        ui.text_edit_singleline(&mut wizard.name);
+                        let valid = !wizard.name.is_empty();
+                        save = ui.add_enabled(valid, Button::new("Save profile")).clicked();
+                        if matches!(status.ui, DScopeUi::Show { .. }) {
+                            use_for_visit = ui
+                                .add_enabled(valid, Button::new("Save and use for this visit"))
+                                .clicked();
+                        }
+                    });
+                }
+                None => {
+                    ui.label("Mark at least two reference points");
+                }
+            }
+        });
+
+    if let Some(fit) = fit.filter(|_| save || use_for_visit) {
+        let profile = CalibrationProfile {
+            name: wizard.name.clone(),
+            ..fit.profile
+        };
+        status.calibrations.add(profile.clone());
+        if let Err(error) = status.calibrations.save() {
+            status.error = Some(error);
+        }
+        if let (
+            true,
+            DScopeUi::Show {
+                photos,
+                current_photo_index,
+                current_photo,
+                compare,
+                ..
+            },
+        ) = (use_for_visit, &mut status.ui)
+        {
+            photos.set_calibration(profile);
+            *compare = None;
+            let index = *current_photo_index;
+            if let Err(error) = select_photo(
+                photos,
+                index,
+                status.remove_hair,
+                current_photo_index,
+                current_photo,
+            ) {
+                status.error = Some(error);
+            }
+        }
+        open = false;
+    }
+    if !open {
+        status.calibration_wizard = None;
+    }
+}
+
+fn colour_window(ctx: &egui::Context, status: &mut DScopeStatus) {
+    let wizard = match &mut status.colour_wizard {
+        Some(wizard) => wizard,
+        None => return,
+    };
+    let (photos, current_photo_index, current_photo) = match &mut status.ui {
+        DScopeUi::Show {
+            photos,
+            current_photo_index,
+            current_photo,
+            ..
+        } => (photos, current_photo_index, current_photo),
+        DScopeUi::Empty => {
+            status.colour_wizard = None;
+            return;
+        }
+    };
+
+    let mut open = true;
+    let mut colour_matrix = None;
+    let fit = ColourMatrix::fit(&wizard.samples);
+    egui::Window::new("Colour calibration")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Next patch");
+                ComboBox::from_id_source("colour-reference")
+                    .selected_text(wizard.reference.name())
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            &mut wizard.reference,
+                            ColourReference::Neutral,
+                            ColourReference::Neutral.name(),
+                        );
+                        for (index, (name, _)) in COLOUR_CHECKER.iter().enumerate() {
+                            ui.selectable_value(
+                                &mut wizard.reference,
+                                ColourReference::Patch(index),
+                                *name,
+                            );
+                        }
+                    });
+                ui.label("then click it on the photo");
+            });
+
+            let (width, height) = wizard.image.dimensions();
+            let half = [width as f64 / 2.0, height as f64 / 2.0];
+            Plot::new("colour-plot")
+                .data_aspect(1.0)
+                .height(300.0)
+                .allow_scroll(false)
+                .show_axes([false, false])
+                .show(ui, |plot| {
+                    plot_photo(plot, ctx, &wizard.photo, 1.0, Color32::WHITE);
+                    if plot.plot_clicked() {
+                        if let Some(point) = plot.pointer_coordinate() {
+                            let (x, y) = (point.x + half[0], half[1] - point.y);
+                            if x >= 0.0 && y >= 0.0 && x < width as f64 && y < height as f64 {
+                                wizard.samples.push(ColourSample::new(
+                                    &wizard.image,
+                                    x as u32,
+                                    y as u32,
+                                    wizard.reference,
+                                ));
+                                if let ColourReference::Patch(index) = wizard.reference {
+                                    wizard.reference =
+                                        ColourReference::Patch((index + 1) % COLOUR_CHECKER.len());
                                 }
                             }
-                        });
+                        }
+                    }
+                    plot.points(
+                        Points::new(Values::from_values(
+                            wizard
+                                .samples
+                                .iter()
+                                .map(|sample| {
+                                    Value::new(
+                                        sample.x as f64 + 0.5 - half[0],
+                                        half[1] - sample.y as f64 - 0.5,
+                                    )
+                                })
+                                .collect(),
+                        ))
+                        .radius(4.0)
+                        .color(Color32::WHITE),
+                    );
                 });
+
+            let mut remove = None;
+            egui::Grid::new("colour-samples").show(ui, |ui| {
+                for (index, sample) in wizard.samples.iter().enumerate() {
+                    let colour =
+                        |rgb: [f32; 3]| Color32::from_rgb(rgb[0] as u8, rgb[1] as u8, rgb[2] as u8);
+                    ui.label(sample.reference.name());
+                    ui.colored_label(colour(sample.measured), "■ measured");
+                    if let Some(fit) = &fit {
+                        ui.colored_label(colour(fit.apply_srgb(sample.measured)), "■ corrected");
+                    }
+                    if ui.button("Remove").clicked() {
+                        remove = Some(index);
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(index) = remove {
+                wizard.samples.remove(index);
             }
+
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(fit.is_some(), Button::new("Apply to visit"))
+                    .clicked()
+                {
+                    colour_matrix = Some(fit);
+                }
+                if ui
+                    .add_enabled(
+                        photos.info.colour_matrix.is_some(),
+                        Button::new("Reset visit colours"),
+                    )
+                    .clicked()
+                {
+                    colour_matrix = Some(None);
+                }
+            });
+        });
+
+    if let Some(colour_matrix) = colour_matrix {
+        photos.set_colour_matrix(colour_matrix);
+        let index = *current_photo_index;
+        if let Err(error) = select_photo(
+            photos,
+            index,
+            status.remove_hair,
+            current_photo_index,
+            current_photo,
+        ) {
+            status.error = Some(error);
         }
+        open = false;
+    }
+    if !open {
+        status.colour_wizard = None;
     }
 }
 
-fn circle(x: f32, y: f32, r: f32, n: usize) -> impl Iterator<Item = Value> {
-    let arc = if n == 0 { PI } else { 2.0 * PI / (n as f32) };
-    (0..=n)
-        .into_iter()
-        .map(move |i| i as f32 * arc)
-        .map(move |arc| Value {
-            x: (x + (r * arc.cos())) as f64,
-            y: (y + (r * arc.sin())) as f64,
-        })
+fn archive_panel(ctx: &egui::Context, status: &mut DScopeStatus) {
+    let archive = match &mut status.archive {
+        Some(archive) => archive,
+        None => return,
+    };
+    let current_visit_patient_id = match &status.ui {
+        DScopeUi::Show { photos, .. } => Some(photos.info.patient_id.clone()),
+        DScopeUi::Empty => None,
+    };
+    let current_photo_mole_id = match &status.ui {
+        DScopeUi::Show {
+            photos,
+            current_photo_index,
+            ..
+        } => photos.photos[*current_photo_index].info.mole_id,
+        DScopeUi::Empty => None,
+    };
+
+    egui::SidePanel::right("archive").show(ctx, |ui| {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.heading("Patients");
+                if ui
+                    .add_enabled(status.new_patient.is_none(), Button::new("New"))
+                    .clicked()
+                {
+                    status.new_patient = Some(NewPatient {
+                        name: String::new(),
+                        surname: String::new(),
+                    });
+                }
+                if ui.button("Close").clicked() {
+                    status.current_patient_index = None;
+                    status.new_patient = None;
+                }
+            });
+
+            let mut create = false;
+            let mut cancel = false;
+            if let Some(new_patient) = &mut status.new_patient {
+                ui.horizontal(|ui| {
+                    ui.label("Surname");
+                    ui.text_edit_singleline(&mut new_patient.surname);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut new_patient.name);
+                });
+                ui.horizontal(|ui| {
+                    create = ui.button("Create").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            }
+            if create {
+                let new_patient = status.new_patient.take().unwrap();
+                match archive.add_patient(new_patient.name, new_patient.surname) {
+                    Ok(index) => status.current_patient_index = Some(index),
+                    Err(error) => status.error = Some(error),
+                }
+            }
+            if cancel {
+                status.new_patient = None;
+            }
+
+            ui.separator();
+            for (index, patient) in archive.patients.iter().enumerate() {
+                let selected = status.current_patient_index == Some(index);
+                if ui
+                    .selectable_label(selected, patient.info.display_name())
+                    .clicked()
+                {
+                    status.current_patient_index = Some(index);
+                }
+            }
+            unreadable_list(ui, "patients", &archive.unreadable_patients);
+
+            if let Some(patient) = status
+                .current_patient_index
+                .and_then(|index| archive.patients.get(index))
+            {
+                ui.separator();
+                ui.heading(patient.info.display_name());
+                ui.label(format!("ID {}", patient.info.id));
+                if ui.button("Body map").clicked() {
+                    status.body_map = Some(BodyMap {
+                        view: BodyView::Front,
+                        place_mole_id: current_photo_mole_id,
+                    });
+                }
+                if let Some(patient_id) = &current_visit_patient_id {
+                    if patient_id.as_ref() != Some(&patient.info.id)
+                        && ui.button("Add current visit").clicked()
+                    {
+                        status.add_visit = true;
+                    }
+                }
+                ui.label("Visits");
+                for visit in patient.visits.iter() {
+                    let label = format!(
+                        "{} ({} photos)",
+                        DisplayTime::new(visit.time),
+                        visit.photo_count
+                    );
+                    if ui.button(label).on_hover_text(&visit.notes).clicked() {
+                        status.load = Some(visit.path.clone());
+                    }
+                }
+                unreadable_list(ui, "visits", &patient.unreadable_visits);
+            }
+        });
+    });
+}
+
+fn unreadable_list(ui: &mut egui::Ui, entries: &str, errors: &[DScopeError]) {
+    if errors.is_empty() {
+        return;
+    }
+    CollapsingHeader::new(format!("{} {} cannot be read", errors.len(), entries))
+        .id_source(format!("unreadable-{}", entries))
+        .show(ui, |ui| {
+            for error in errors {
+                ui.label(error.to_string());
+            }
+        });
 }
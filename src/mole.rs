@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::{
+    body_map::BodyMarker, calibration::CalibrationProfile, colour::ColourMatrix,
+    photo_set::PhotoInfo,
+};
+
+pub type MoleId = usize;
+
+/// A lesion followed over time, owned by the patient record.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mole {
+    pub id: MoleId,
+    pub label: String,
+    pub location: String,
+    /// Where the mole is on the body map, once placed.
+    #[serde(default)]
+    pub marker: Option<BodyMarker>,
+}
+
+impl Mole {
+    pub fn display_name(&self) -> String {
+        if self.label.is_empty() {
+            format!("#{}", self.id)
+        } else {
+            format!("#{} {}", self.id, self.label)
+        }
+    }
+}
+
+/// Returns the id after the highest of `moles`, leaving gaps in the ids unused so that
+/// photos of a mole missing from the list are never linked to a new one.
+pub fn next_mole_id(moles: &[Mole]) -> MoleId {
+    moles.iter().map(|mole| mole.id + 1).max().unwrap_or(1)
+}
+
+#[test]
+fn test_next_mole_id() {
+    let mole = |id| Mole {
+        id,
+        label: String::new(),
+        location: String::new(),
+        marker: None,
+    };
+    assert_eq!(next_mole_id(&[]), 1);
+    assert_eq!(next_mole_id(&[mole(1)]), 2);
+    assert_eq!(next_mole_id(&[mole(3), mole(1)]), 4);
+}
+
+/// One photo of a mole, taken during one of the visits of the patient.
+#[derive(Debug, Clone)]
+pub struct MoleObservation {
+    pub visit_path: PathBuf,
+    pub photo_id: usize,
+    pub info: PhotoInfo,
+    /// Calibration the visit was measured with.
+    pub calibration: CalibrationProfile,
+    pub colour_matrix: Option<ColourMatrix>,
+}
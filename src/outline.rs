@@ -0,0 +1,261 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+pub type Point = [f32; 2];
+
+/// Points sampled on each segment of a spline outline.
+const SPLINE_SAMPLES: usize = 8;
+
+/// The shape used to measure a mole, in millimetres around the mole center.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MoleShape {
+    /// A circle of the mole metrics diameter, the only shape sized by it.
+    #[default]
+    Circle,
+    Ellipse {
+        major_axis: f32,
+        minor_axis: f32,
+        /// Angle of the major axis in radians, counterclockwise from the X axis.
+        rotation: f32,
+    },
+    /// A free form outline; the points are absolute plot coordinates.
+    Outline { points: Vec<Point>, spline: bool },
+}
+
+impl MoleShape {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MoleShape::Circle => "Circle",
+            MoleShape::Ellipse { .. } => "Ellipse",
+            MoleShape::Outline { .. } => "Outline",
+        }
+    }
+}
+
+/// Measures derived from a mole shape, in millimetres.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub struct OutlineMeasures {
+    pub area: f32,
+    pub perimeter: f32,
+    pub major_axis: f32,
+    pub minor_axis: f32,
+    /// Diameter of the circle with the same area.
+    pub equivalent_diameter: f32,
+}
+
+impl OutlineMeasures {
+    fn from_area(area: f32, perimeter: f32, major_axis: f32, minor_axis: f32) -> Self {
+        Self {
+            area,
+            perimeter,
+            major_axis,
+            minor_axis,
+            equivalent_diameter: 2.0 * (area / PI).sqrt(),
+        }
+    }
+
+    pub fn circle(diameter: f32) -> Self {
+        let radius = diameter / 2.0;
+        Self {
+            area: PI * radius * radius,
+            perimeter: PI * diameter,
+            major_axis: diameter,
+            minor_axis: diameter,
+            equivalent_diameter: diameter,
+        }
+    }
+
+    pub fn ellipse(major_axis: f32, minor_axis: f32) -> Self {
+        let a = major_axis / 2.0;
+        let b = minor_axis / 2.0;
+        // Ramanujan's approximation of the ellipse perimeter.
+        let h = ((a - b) / (a + b)).powi(2);
+        let perimeter = if a + b > 0.0 {
+            PI * (a + b) * (1.0 + 3.0 * h / (10.0 + (4.0 - 3.0 * h).sqrt()))
+        } else {
+            0.0
+        };
+        Self::from_area(PI * a * b, perimeter, major_axis, minor_axis)
+    }
+
+    /// Measures of a closed polygon; the major axis is its largest Feret diameter and the
+    /// minor axis its width perpendicular to it.
+    pub fn polygon(points: &[Point]) -> Self {
+        if points.len() < 3 {
+            return Default::default();
+        }
+        let (major_axis, direction) = feret_diameter(points);
+        let minor_axis = width_along(points, [-direction[1], direction[0]]);
+        Self::from_area(
+            polygon_area(points),
+            polygon_perimeter(points),
+            major_axis,
+            minor_axis,
+        )
+    }
+}
+
+pub fn polygon_area(points: &[Point]) -> f32 {
+    let mut sum = 0.0;
+    for (index, p1) in points.iter().enumerate() {
+        let p2 = points[(index + 1) % points.len()];
+        sum += p1[0] * p2[1] - p2[0] * p1[1];
+    }
+    (sum / 2.0).abs()
+}
+
+pub fn polygon_perimeter(points: &[Point]) -> f32 {
+    points
+        .iter()
+        .enumerate()
+        .map(|(index, p1)| distance(*p1, points[(index + 1) % points.len()]))
+        .sum()
+}
+
+/// Area centroid of a closed polygon, falling back to the vertex average for
+/// degenerate polygons.
+pub fn polygon_centroid(points: &[Point]) -> Option<Point> {
+    if points.is_empty() {
+        return None;
+    }
+    let mut area = 0.0;
+    let mut cx = 0.0;
+    let mut cy = 0.0;
+    for (index, p1) in points.iter().enumerate() {
+        let p2 = points[(index + 1) % points.len()];
+        let cross = p1[0] * p2[1] - p2[0] * p1[1];
+        area += cross;
+        cx += (p1[0] + p2[0]) * cross;
+        cy += (p1[1] + p2[1]) * cross;
+    }
+    if area.abs() > f32::EPSILON {
+        Some([cx / (3.0 * area), cy / (3.0 * area)])
+    } else {
+        let n = points.len() as f32;
+        Some([
+            points.iter().map(|p| p[0]).sum::<f32>() / n,
+            points.iter().map(|p| p[1]).sum::<f32>() / n,
+        ])
+    }
+}
+
+fn distance(p1: Point, p2: Point) -> f32 {
+    ((p2[0] - p1[0]).powi(2) + (p2[1] - p1[1]).powi(2)).sqrt()
+}
+
+/// Largest distance between two points, with the unit direction joining them.
+fn feret_diameter(points: &[Point]) -> (f32, Point) {
+    let mut best = (0.0, [1.0, 0.0]);
+    for (index, p1) in points.iter().enumerate() {
+        for p2 in points[index + 1..].iter() {
+            let d = distance(*p1, *p2);
+            if d > best.0 {
+                best = (d, [(p2[0] - p1[0]) / d, (p2[1] - p1[1]) / d]);
+            }
+        }
+    }
+    best
+}
+
+/// Extent of the projection of the points on a unit direction.
+fn width_along(points: &[Point], direction: Point) -> f32 {
+    let projections = points
+        .iter()
+        .map(|p| p[0] * direction[0] + p[1] * direction[1]);
+    let (min, max) = projections.fold((f32::MAX, f32::MIN), |(min, max), v| {
+        (min.min(v), max.max(v))
+    });
+    if max >= min {
+        max - min
+    } else {
+        0.0
+    }
+}
+
+/// Samples a closed Catmull-Rom spline through the points.
+pub fn spline_points(points: &[Point]) -> Vec<Point> {
+    let n = points.len();
+    if n < 3 {
+        return points.to_vec();
+    }
+    let mut result = Vec::with_capacity(n * SPLINE_SAMPLES);
+    for index in 0..n {
+        let p0 = points[(index + n - 1) % n];
+        let p1 = points[index];
+        let p2 = points[(index + 1) % n];
+        let p3 = points[(index + 2) % n];
+        for sample in 0..SPLINE_SAMPLES {
+            let t = sample as f32 / SPLINE_SAMPLES as f32;
+            let t2 = t * t;
+            let t3 = t2 * t;
+            let mut point = [0.0; 2];
+            for (axis, value) in point.iter_mut().enumerate() {
+                *value = 0.5
+                    * (2.0 * p1[axis]
+                        + (-p0[axis] + p2[axis]) * t
+                        + (2.0 * p0[axis] - 5.0 * p1[axis] + 4.0 * p2[axis] - p3[axis]) * t2
+                        + (-p0[axis] + 3.0 * p1[axis] - 3.0 * p2[axis] + p3[axis]) * t3);
+            }
+            result.push(point);
+        }
+    }
+    result
+}
+
+/// Points on the ellipse with the given full axes, center and rotation.
+pub fn ellipse_points(
+    center: Point,
+    major_axis: f32,
+    minor_axis: f32,
+    rotation: f32,
+    n: usize,
+) -> Vec<Point> {
+    let (sin, cos) = rotation.sin_cos();
+    (0..n)
+        .map(|i| 2.0 * PI * i as f32 / n as f32)
+        .map(|angle| {
+            let x = major_axis / 2.0 * angle.cos();
+            let y = minor_axis / 2.0 * angle.sin();
+            [center[0] + x * cos - y * sin, center[1] + x * sin + y * cos]
+        })
+        .collect()
+}
+
+#[test]
+fn test_polygon_measures() {
+    let square = [[0.0, 0.0], [2.0, 0.0], [2.0, 1.0], [0.0, 1.0]];
+    let measures = OutlineMeasures::polygon(&square);
+    assert_eq!(measures.area, 2.0);
+    assert_eq!(measures.perimeter, 6.0);
+    assert!((measures.major_axis - 5.0f32.sqrt()).abs() < 1e-5);
+    assert!((measures.equivalent_diameter - 2.0 * (2.0 / PI).sqrt()).abs() < 1e-5);
+    assert_eq!(polygon_centroid(&square), Some([1.0, 0.5]));
+}
+
+#[test]
+fn test_ellipse_measures() {
+    let circle = OutlineMeasures::ellipse(2.0, 2.0);
+    assert!((circle.area - PI).abs() < 1e-5);
+    assert!((circle.perimeter - 2.0 * PI).abs() < 1e-5);
+    assert!((circle.equivalent_diameter - 2.0).abs() < 1e-5);
+
+    let points = ellipse_points([1.0, 1.0], 4.0, 2.0, PI / 2.0, 256);
+    let polygon = OutlineMeasures::polygon(&points);
+    let ellipse = OutlineMeasures::ellipse(4.0, 2.0);
+    assert!((polygon.area - ellipse.area).abs() < 0.01);
+    assert!((polygon.perimeter - ellipse.perimeter).abs() < 0.01);
+    assert!((polygon.major_axis - 4.0).abs() < 0.01);
+    assert!((polygon.minor_axis - 2.0).abs() < 0.01);
+}
+
+#[test]
+fn test_spline_points() {
+    let square = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+    let spline = spline_points(&square);
+    assert_eq!(spline.len(), 4 * SPLINE_SAMPLES);
+    // The spline passes through the control points.
+    for (index, point) in square.iter().enumerate() {
+        assert_eq!(&spline[index * SPLINE_SAMPLES], point);
+    }
+}
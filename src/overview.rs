@@ -0,0 +1,140 @@
+use egui_extras::RetainedImage;
+use image::load_from_memory;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{DScopeError, DScopeResult},
+    outline::Point,
+    photo_set::retained_image,
+};
+
+/// Extensions of the overview photos taken alongside the close-ups.
+const OVERVIEW_FILE_EXTENSIONS: [&str; 3] = [".jpg", ".jpeg", ".png"];
+/// Largest distance between a click and the marker it selects, in widths of the photo.
+const MARKER_RADIUS: f32 = 0.02;
+
+/// Whether a file of a visit that is not a close-up is an overview photo.
+pub fn is_overview_file_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    !name.starts_with('.')
+        && OVERVIEW_FILE_EXTENSIONS
+            .iter()
+            .any(|extension| name.ends_with(extension))
+}
+
+#[test]
+fn test_is_overview_file_name() {
+    assert!(is_overview_file_name("back.jpg"));
+    assert!(is_overview_file_name("IMG_2041.JPEG"));
+    assert!(is_overview_file_name("left arm.png"));
+    assert!(!is_overview_file_name("info.json"));
+    assert!(!is_overview_file_name(".thumb.jpg"));
+}
+
+/// A lesion marked on an overview photo, with the close-ups of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OverviewMarker {
+    /// In widths of the photo, from its center, the Y axis upwards.
+    pub position: Point,
+    /// Close-ups of the lesion in the visit, by id.
+    pub photo_ids: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OverviewInfo {
+    pub markers: Vec<OverviewMarker>,
+}
+
+impl OverviewInfo {
+    /// Index of the marker closest to `point`, if close enough to be clicked.
+    pub fn marker_at(&self, point: Point) -> Option<usize> {
+        self.markers
+            .iter()
+            .enumerate()
+            .map(|(index, marker)| {
+                let distance = (marker.position[0] - point[0]).hypot(marker.position[1] - point[1]);
+                (index, distance)
+            })
+            .filter(|(_, distance)| *distance <= MARKER_RADIUS)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index)
+    }
+
+    /// Links a close-up to the marker at `point`, or to a new marker there, and unlinks it
+    /// from the others, as a close-up shows a single lesion.
+    pub fn mark(&mut self, point: Point, photo_id: usize) {
+        let index = match self.marker_at(point) {
+            Some(index) => index,
+            None => {
+                self.markers.push(OverviewMarker {
+                    position: point,
+                    photo_ids: Vec::new(),
+                });
+                self.markers.len() - 1
+            }
+        };
+        for (other, marker) in self.markers.iter_mut().enumerate() {
+            if other != index {
+                marker.photo_ids.retain(|id| *id != photo_id);
+            }
+        }
+        if !self.markers[index].photo_ids.contains(&photo_id) {
+            self.markers[index].photo_ids.push(photo_id);
+        }
+        self.markers.retain(|marker| !marker.photo_ids.is_empty());
+    }
+
+    /// Unlinks a close-up from its marker, removing the marker if it has no other.
+    pub fn unlink(&mut self, photo_id: usize) {
+        for marker in self.markers.iter_mut() {
+            marker.photo_ids.retain(|id| *id != photo_id);
+        }
+        self.markers.retain(|marker| !marker.photo_ids.is_empty());
+    }
+
+    pub fn is_linked(&self, photo_id: usize) -> bool {
+        self.markers
+            .iter()
+            .any(|marker| marker.photo_ids.contains(&photo_id))
+    }
+}
+
+#[test]
+fn test_overview_marks() {
+    let mut info = OverviewInfo::default();
+    info.mark([0.1, 0.1], 3);
+    info.mark([0.105, 0.1], 4);
+    info.mark([-0.2, 0.0], 5);
+    assert_eq!(info.markers.len(), 2);
+    assert_eq!(info.markers[0].photo_ids, vec![3, 4]);
+
+    // Moving a close-up to another lesion, then the last one away from its marker.
+    info.mark([-0.2, 0.0], 4);
+    assert_eq!(info.markers[0].photo_ids, vec![3]);
+    assert_eq!(info.markers[1].photo_ids, vec![5, 4]);
+    info.mark([-0.2, 0.0], 3);
+    assert_eq!(info.markers.len(), 1);
+    assert_eq!(info.marker_at([-0.19, 0.0]), Some(0));
+
+    info.unlink(4);
+    assert!(info.is_linked(3) && !info.is_linked(4));
+    info.unlink(3);
+    info.unlink(5);
+    assert!(info.markers.is_empty());
+}
+
+/// A wide clinical photo of a body area, alongside the close-ups of the visit.
+pub struct OverviewPhoto {
+    pub file_name: String,
+    pub bytes: Vec<u8>,
+    pub info: OverviewInfo,
+}
+
+impl OverviewPhoto {
+    /// The photo to display next to the close-ups.
+    pub fn image(&self) -> DScopeResult<RetainedImage> {
+        let image = load_from_memory(&self.bytes)
+            .map_err(|error| DScopeError::cannot_decode_image(error, self.file_name.clone()))?;
+        Ok(retained_image(&self.file_name, &image.to_rgb8()))
+    }
+}
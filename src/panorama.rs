@@ -0,0 +1,148 @@
+use image::RgbImage;
+
+use crate::{
+    correction::sample,
+    registration::{register_overlapping, Alignment},
+    segmentation::field_of_view,
+};
+
+/// Stitches overlapping corrected photos of a lesion too large for the scope into one
+/// mosaic of the same scale `px_per_mm`, in the orientation of the first photo.
+///
+/// Each photo is registered to one already placed, so that a chain of overlaps is
+/// enough. Where photos overlap, the one whose center is closest is used, as photos are
+/// sharpest and least distorted near their center. Fails with the index of a photo that
+/// overlaps none of the others.
+pub fn stitch(images: &[RgbImage], px_per_mm: f32) -> Result<RgbImage, usize> {
+    // Alignments from the millimetres of each photo to those of the first one.
+    let mut placed = vec![(0, Alignment::default())];
+    let mut pending: Vec<usize> = (1..images.len()).collect();
+    while !pending.is_empty() {
+        let found = pending.iter().enumerate().find_map(|(position, index)| {
+            placed.iter().find_map(|(placed_index, to_first)| {
+                register_overlapping(
+                    &images[*placed_index],
+                    px_per_mm,
+                    &images[*index],
+                    px_per_mm,
+                )
+                .map(|(alignment, _)| (position, to_first.after(&alignment)))
+            })
+        });
+        match found {
+            Some((position, alignment)) => placed.push((pending.remove(position), alignment)),
+            None => return Err(pending[0]),
+        }
+    }
+
+    let half_size = |image: &RgbImage| {
+        [
+            image.width() as f32 / 2.0 / px_per_mm,
+            image.height() as f32 / 2.0 / px_per_mm,
+        ]
+    };
+    let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+    for (index, alignment) in &placed {
+        let [half_width, half_height] = half_size(&images[*index]);
+        for corner in [
+            [-half_width, -half_height],
+            [half_width, -half_height],
+            [-half_width, half_height],
+            [half_width, half_height],
+        ] {
+            let point = alignment.apply(corner);
+            for axis in 0..2 {
+                min[axis] = min[axis].min(point[axis]);
+                max[axis] = max[axis].max(point[axis]);
+            }
+        }
+    }
+
+    let frames: Vec<_> = placed
+        .iter()
+        .map(|(index, alignment)| {
+            let image = &images[*index];
+            (
+                image,
+                alignment.inverse(),
+                field_of_view(image.width(), image.height()),
+                half_size(image),
+            )
+        })
+        .collect();
+    let width = ((max[0] - min[0]) * px_per_mm).ceil() as u32;
+    let height = ((max[1] - min[1]) * px_per_mm).ceil() as u32;
+    Ok(RgbImage::from_fn(width, height, |x, y| {
+        let point = [
+            min[0] + (x as f32 + 0.5) / px_per_mm,
+            max[1] - (y as f32 + 0.5) / px_per_mm,
+        ];
+        frames
+            .iter()
+            .filter_map(|(image, from_first, view, [half_width, half_height])| {
+                let [u, v] = from_first.apply(point);
+                let (px, py) = (
+                    u * px_per_mm + image.width() as f32 / 2.0,
+                    image.height() as f32 / 2.0 - v * px_per_mm,
+                );
+                if px < 0.0 || py < 0.0 || !view.get_signed(px as i64, py as i64) {
+                    return None;
+                }
+                let distance = (u / half_width).powi(2) + (v / half_height).powi(2);
+                Some((distance, sample(image, px - 0.5, py - 0.5)))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map_or(image::Rgb([0, 0, 0]), |(_, pixel)| pixel)
+    }))
+}
+
+#[test]
+fn test_stitch() {
+    // A lesion wider than the photos, with spots so that the overlaps are not ambiguous.
+    let scene = |p: [f32; 2]| {
+        let in_lesion = (p[0] / 8.0).powi(2) + (p[1] / 3.0).powi(2) <= 1.0;
+        let in_spot = [[-5.0, 2.5], [-1.0, -2.0], [2.5, 3.0], [6.0, -1.5]]
+            .iter()
+            .any(|spot: &[f32; 2]| (p[0] - spot[0]).hypot(p[1] - spot[1]) <= 0.6);
+        if in_lesion != in_spot {
+            image::Rgb([90, 60, 40])
+        } else {
+            image::Rgb([220, 180, 160])
+        }
+    };
+    let shot = |center_x: f32| {
+        RgbImage::from_fn(256, 192, |x, y| {
+            scene([
+                (x as f32 + 0.5 - 128.0) / 20.0 + center_x,
+                (96.0 - y as f32 - 0.5) / 20.0,
+            ])
+        })
+    };
+
+    let mosaic = stitch(&[shot(-3.0), shot(3.0)], 20.0).unwrap();
+    // The photos span 6 mm, 120 pixels, more than one photo.
+    assert!(
+        (mosaic.width() as i32 - 256 - 120).abs() <= 4,
+        "{}",
+        mosaic.width()
+    );
+    assert!(
+        (mosaic.height() as i32 - 192).abs() <= 4,
+        "{}",
+        mosaic.height()
+    );
+    let (mut count, mut wrong) = (0, 0);
+    for (x, y, pixel) in mosaic.enumerate_pixels() {
+        let expected = scene([
+            (x as f32 + 0.5 - mosaic.width() as f32 / 2.0) / 20.0,
+            (mosaic.height() as f32 / 2.0 - y as f32 - 0.5) / 20.0,
+        ]);
+        if pixel.0 != [0, 0, 0] {
+            count += 1;
+            if (pixel[1] as i32 - expected[1] as i32).abs() > 40 {
+                wrong += 1;
+            }
+        }
+    }
+    assert!(wrong * 50 < count, "{} of {}", wrong, count);
+}
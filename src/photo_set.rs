@@ -1,20 +1,47 @@
 use chrono::{Datelike, NaiveDateTime, Timelike};
 use eframe::epaint::ColorImage;
 use egui_extras::RetainedImage;
-use image::{load_from_memory, EncodableLayout};
+use image::{load_from_memory, DynamicImage, EncodableLayout, ImageOutputFormat, RgbImage};
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeMap, path::PathBuf, time::SystemTime};
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
-use crate::errors::{DScopeError, DScopeResult};
+use crate::{
+    abcd::AbcdScore,
+    burst::{difference_hash, group_bursts, Burst, BurstShot},
+    calibration::CalibrationProfile,
+    change::ChangeSummary,
+    colour::ColourMatrix,
+    correction::correct_photo,
+    errors::{DScopeError, DScopeResult},
+    findings::SevenPointChecklist,
+    mole::MoleId,
+    outline::{
+        ellipse_points, polygon_area, polygon_centroid, spline_points, MoleShape, OutlineMeasures,
+        Point,
+    },
+    overview::{is_overview_file_name, OverviewInfo, OverviewPhoto},
+    quality::PhotoQuality,
+    registration::{Alignment, Registration},
+    relocation::{verify_files, Relocation},
+    segmentation::segment_lesion,
+};
 
 const INFO_FILE_NAME: &str = "info.json";
+const INFO_BACKUP_FILE_NAME: &str = "info.json.bak";
+const TEMPORARY_FILE_SUFFIX: &str = ".tmp";
 const PHOTO_FILE_NAME_PREFIX: &str = "PICT";
 const PHOTO_FILE_NAME_SUFFIX: &str = ".jpg";
 const PREVIEW_WIDTH: u32 = 128;
-
-pub const MOLE_CENTER_DISTANCE_MAX: f32 = 2.0;
-pub const MOLE_SIZE_MAX: f32 = 4.0;
-pub const PHOTO_PX_PER_MM: f32 = 1250.0;
+const DERIVED_PHOTO_QUALITY: u8 = 95;
+const SEEDED_OUTLINE_POINTS: usize = 16;
+pub const SCHEMA_VERSION: u64 = MIGRATIONS.len() as u64;
+/// Upgrades from each version to the next, applied to the JSON before decoding.
+const MIGRATIONS: &[fn(&mut serde_json::Value)] = &[migrate_v0];
 
 pub fn photo_file_name(id: usize) -> String {
     format!(
@@ -70,11 +97,15 @@ fn test_photo_file_id() {
     assert_eq!(photo_file_id("PICT000.jpg"), None);
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct MoleMetrics {
     pub center_x: f32,
     pub center_y: f32,
+    /// Equivalent diameter of the shapes other than the circle.
     pub diameter: f32,
+    pub shape: MoleShape,
+    pub measures: Option<OutlineMeasures>,
 }
 
 impl MoleMetrics {
@@ -85,13 +116,213 @@ impl MoleMetrics {
             None
         }
     }
+
+    pub fn area(&self) -> Option<f32> {
+        self.measures
+            .map(|measures| measures.area)
+            .filter(|area| *area > 0.0)
+    }
+
+    pub fn update_measures(&mut self) {
+        let measures = match &self.shape {
+            MoleShape::Circle => OutlineMeasures::circle(self.diameter.max(0.0)),
+            MoleShape::Ellipse {
+                major_axis,
+                minor_axis,
+                ..
+            } => OutlineMeasures::ellipse(*major_axis, *minor_axis),
+            MoleShape::Outline { .. } => {
+                let points = self.outline_points(0);
+                if let Some(center) = polygon_centroid(&points) {
+                    self.center_x = center[0];
+                    self.center_y = center[1];
+                }
+                OutlineMeasures::polygon(&points)
+            }
+        };
+        if self.shape != MoleShape::Circle {
+            self.diameter = measures.equivalent_diameter;
+        }
+        self.measures = Some(measures);
+    }
+
+    pub fn switch_shape(&mut self, name: &str) {
+        self.update_measures();
+        self.shape = match name {
+            "Ellipse" => MoleShape::Ellipse {
+                major_axis: self.diameter,
+                minor_axis: self.diameter,
+                rotation: 0.0,
+            },
+            "Outline" => {
+                // Scaled so that the polygon keeps the area of the curve it replaces.
+                let points = self.outline_points(SEEDED_OUTLINE_POINTS);
+                let area = polygon_area(&points);
+                let scale = match self.measures {
+                    Some(measures) if area > 0.0 => (measures.area / area).sqrt(),
+                    _ => 1.0,
+                };
+                let center = [self.center_x, self.center_y];
+                MoleShape::Outline {
+                    points: points
+                        .iter()
+                        .map(|point| {
+                            [
+                                center[0] + (point[0] - center[0]) * scale,
+                                center[1] + (point[1] - center[1]) * scale,
+                            ]
+                        })
+                        .collect(),
+                    spline: false,
+                }
+            }
+            _ => MoleShape::Circle,
+        };
+        self.update_measures();
+    }
+
+    pub fn scale(&mut self, factor: f32) {
+        self.center_x *= factor;
+        self.center_y *= factor;
+        self.diameter *= factor;
+        match &mut self.shape {
+            MoleShape::Circle => {}
+            MoleShape::Ellipse {
+                major_axis,
+                minor_axis,
+                ..
+            } => {
+                *major_axis *= factor;
+                *minor_axis *= factor;
+            }
+            MoleShape::Outline { points, .. } => {
+                for point in points.iter_mut() {
+                    point[0] *= factor;
+                    point[1] *= factor;
+                }
+            }
+        }
+        self.update_measures();
+    }
+
+    pub fn align(&mut self, alignment: &Alignment) {
+        let center = alignment.apply([self.center_x, self.center_y]);
+        self.center_x = center[0];
+        self.center_y = center[1];
+        self.diameter *= alignment.scale;
+        match &mut self.shape {
+            MoleShape::Circle => {}
+            MoleShape::Ellipse {
+                major_axis,
+                minor_axis,
+                rotation,
+            } => {
+                *major_axis *= alignment.scale;
+                *minor_axis *= alignment.scale;
+                *rotation += alignment.rotation;
+            }
+            MoleShape::Outline { points, .. } => {
+                for point in points.iter_mut() {
+                    *point = alignment.apply(*point);
+                }
+            }
+        }
+        self.update_measures();
+    }
+
+    pub fn outline_points(&self, n: usize) -> Vec<Point> {
+        let center = [self.center_x, self.center_y];
+        match &self.shape {
+            MoleShape::Circle => ellipse_points(center, self.diameter, self.diameter, 0.0, n),
+            MoleShape::Ellipse {
+                major_axis,
+                minor_axis,
+                rotation,
+            } => ellipse_points(center, *major_axis, *minor_axis, *rotation, n),
+            MoleShape::Outline { points, spline } => {
+                if *spline {
+                    spline_points(points)
+                } else {
+                    points.clone()
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_mole_metrics_circle_compatibility() {
+    let mut metrics: MoleMetrics =
+        serde_json::from_str(r#"{"center_x":0.5,"center_y":-0.5,"diameter":2.0}"#).unwrap();
+    assert_eq!(metrics.shape, MoleShape::Circle);
+    assert_eq!(metrics.area(), None);
+    metrics.update_measures();
+    assert_eq!(metrics.size(), Some(2.0));
+    assert!((metrics.area().unwrap() - std::f32::consts::PI).abs() < 1e-5);
+
+    metrics.shape = MoleShape::Outline {
+        points: vec![[0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0]],
+        spline: false,
+    };
+    metrics.update_measures();
+    assert_eq!((metrics.center_x, metrics.center_y), (1.0, 1.0));
+    assert_eq!(metrics.area(), Some(4.0));
+}
+
+#[test]
+fn test_mole_metrics_switch_shape() {
+    let mut metrics = MoleMetrics {
+        center_x: 0.5,
+        center_y: -0.5,
+        diameter: 2.0,
+        ..Default::default()
+    };
+    for name in [
+        "Ellipse", "Circle", "Outline", "Circle", "Outline", "Ellipse",
+    ] {
+        metrics.switch_shape(name);
+        assert_eq!(metrics.shape.name(), name);
+        assert!((metrics.center_x - 0.5).abs() < 1e-5, "{}", name);
+        assert!((metrics.center_y + 0.5).abs() < 1e-5, "{}", name);
+        assert!((metrics.diameter - 2.0).abs() < 1e-4, "{}", name);
+    }
+}
+
+#[test]
+fn test_mole_metrics_scale() {
+    let mut metrics = MoleMetrics {
+        shape: MoleShape::Outline {
+            points: vec![[0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0]],
+            spline: false,
+        },
+        ..Default::default()
+    };
+    metrics.scale(0.5);
+    assert_eq!((metrics.center_x, metrics.center_y), (0.5, 0.5));
+    assert_eq!(metrics.area(), Some(1.0));
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PhotoInfo {
     pub time: SystemTime,
+    #[serde(default)]
     pub notes: String,
+    #[serde(default)]
     pub mole_metrics: MoleMetrics,
+    #[serde(default)]
+    pub mole_id: Option<MoleId>,
+    #[serde(default)]
+    pub abcd: Option<AbcdScore>,
+    #[serde(default)]
+    pub findings: SevenPointChecklist,
+    #[serde(default)]
+    pub registration: Option<Registration>,
+    #[serde(default)]
+    pub change: Option<ChangeSummary>,
+    #[serde(default)]
+    pub flagged: bool,
+    #[serde(default)]
+    pub derived_from: Option<Derivation>,
 }
 
 impl PhotoInfo {
@@ -100,6 +331,51 @@ impl PhotoInfo {
             time,
             notes: String::new(),
             mole_metrics: Default::default(),
+            mole_id: None,
+            abcd: None,
+            findings: Default::default(),
+            registration: None,
+            change: None,
+            flagged: false,
+            derived_from: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Derivation {
+    FocusStack { sources: Vec<usize> },
+    Panorama { sources: Vec<usize> },
+}
+
+impl Derivation {
+    pub fn sources(&self) -> &[usize] {
+        match self {
+            Derivation::FocusStack { sources } | Derivation::Panorama { sources } => sources,
+        }
+    }
+
+    /// Mosaics are built from corrected photos and must not be corrected again.
+    pub fn is_corrected(&self) -> bool {
+        matches!(self, Derivation::Panorama { .. })
+    }
+}
+
+impl std::fmt::Display for Derivation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sources: Vec<String> = self
+            .sources()
+            .iter()
+            .map(|id| photo_file_name(*id))
+            .collect();
+        match self {
+            Derivation::FocusStack { .. } => {
+                f.write_fmt(format_args!("Focus stack of {}", sources.join(", ")))
+            }
+            Derivation::Panorama { .. } => {
+                f.write_fmt(format_args!("Panorama of {}", sources.join(", ")))
+            }
         }
     }
 }
@@ -109,6 +385,96 @@ pub struct Photo {
     pub bytes: Vec<u8>,
     pub preview: RetainedImage,
     pub info: PhotoInfo,
+    pub proposal: Option<MoleMetrics>,
+    pub quality: PhotoQuality,
+    pub hash: u64,
+}
+
+impl Photo {
+    pub fn new(
+        id: usize,
+        bytes: Vec<u8>,
+        info: PhotoInfo,
+        calibration: &CalibrationProfile,
+        colour_matrix: Option<&ColourMatrix>,
+    ) -> DScopeResult<Self> {
+        let image = load_from_memory(&bytes)
+            .map_err(|error| DScopeError::cannot_decode_image(error, photo_file_name(id)))?;
+        let nheight =
+            ((image.height() as f32) * ((PREVIEW_WIDTH as f32) / (image.width() as f32))) as u32;
+        let preview_image = image::imageops::resize(
+            &image,
+            PREVIEW_WIDTH,
+            nheight,
+            image::imageops::FilterType::Nearest,
+        );
+        let preview_color_image = ColorImage::from_rgba_unmultiplied(
+            [PREVIEW_WIDTH as usize, nheight as usize],
+            preview_image.as_bytes(),
+        );
+        let preview = RetainedImage::from_color_image(photo_file_name(id), preview_color_image);
+
+        let corrected = DynamicImage::ImageRgb8(correct(
+            &image,
+            info.derived_from.as_ref(),
+            calibration,
+            colour_matrix,
+        ));
+        let proposal = segment_lesion(&corrected, calibration.px_per_mm);
+        let quality = PhotoQuality::measure(&corrected);
+        let hash = difference_hash(&corrected);
+
+        Ok(Self {
+            id,
+            bytes,
+            preview,
+            info,
+            proposal,
+            quality,
+            hash,
+        })
+    }
+
+    pub fn decode(&self) -> DScopeResult<DynamicImage> {
+        load_from_memory(&self.bytes)
+            .map_err(|error| DScopeError::cannot_decode_image(error, photo_file_name(self.id)))
+    }
+
+    pub fn corrected(
+        &self,
+        calibration: &CalibrationProfile,
+        colour_matrix: Option<&ColourMatrix>,
+    ) -> DScopeResult<RgbImage> {
+        Ok(correct(
+            &self.decode()?,
+            self.info.derived_from.as_ref(),
+            calibration,
+            colour_matrix,
+        ))
+    }
+}
+
+pub fn correct(
+    image: &DynamicImage,
+    derived_from: Option<&Derivation>,
+    calibration: &CalibrationProfile,
+    colour_matrix: Option<&ColourMatrix>,
+) -> RgbImage {
+    match derived_from {
+        Some(derivation) if derivation.is_corrected() => image.to_rgb8(),
+        _ => correct_photo(image, calibration, colour_matrix),
+    }
+}
+
+pub fn retained_image(debug_name: &str, image: &RgbImage) -> RetainedImage {
+    let rgba = DynamicImage::ImageRgb8(image.clone()).to_rgba8();
+    RetainedImage::from_color_image(
+        debug_name,
+        ColorImage::from_rgba_unmultiplied(
+            [image.width() as usize, image.height() as usize],
+            rgba.as_bytes(),
+        ),
+    )
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -117,6 +483,9 @@ pub struct PhotoSetInfo {
     pub surname: String,
     pub time: SystemTime,
     pub notes: String,
+    pub patient_id: Option<String>,
+    pub calibration: CalibrationProfile,
+    pub colour_matrix: Option<ColourMatrix>,
 }
 
 impl Default for PhotoSetInfo {
@@ -126,6 +495,9 @@ impl Default for PhotoSetInfo {
             surname: Default::default(),
             time: std::time::SystemTime::now(),
             notes: Default::default(),
+            patient_id: None,
+            calibration: Default::default(),
+            colour_matrix: None,
         }
     }
 }
@@ -133,10 +505,17 @@ impl Default for PhotoSetInfo {
 pub struct PhotoSet {
     pub path: PathBuf,
     pub photos: Vec<Photo>,
+    pub overviews: Vec<OverviewPhoto>,
     pub info: PhotoSetInfo,
+    /// Set when the info file was restored from its backup, which saving then keeps.
+    pub restored_info: Option<DScopeError>,
 }
 
 impl PhotoSet {
+    pub fn photo_index(&self, id: usize) -> Option<usize> {
+        self.photos.iter().position(|photo| photo.id == id)
+    }
+
     pub fn from_path(path: PathBuf) -> DScopeResult<Self> {
         if !path.is_dir() {
             return Err(DScopeError::expected_directory(
@@ -144,20 +523,32 @@ impl PhotoSet {
             ));
         }
 
+        let (info_data, restored_info) = match PhotoSetData::read(&path)? {
+            Some((data, restored_info)) => (Some(data), restored_info),
+            None => (None, None),
+        };
+        let calibration = info_data
+            .as_ref()
+            .map(|data| data.calibration.clone())
+            .unwrap_or_default();
+        let colour_matrix = info_data.as_ref().and_then(|data| data.colour_matrix);
+
         let files = path.read_dir().map_err(|error| {
             DScopeError::cannot_read_file(error, path.clone().to_string_lossy().to_string())
         })?;
         let mut photos = Vec::new();
+        let mut overviews = Vec::new();
         for file in files.into_iter() {
             let file = match file {
                 Ok(file) => file,
                 Err(_) => continue,
             };
 
-            let id = match photo_file_id(&file.file_name().to_string_lossy().to_string()) {
-                Some(id) => id,
-                None => continue,
-            };
+            let file_name = file.file_name().to_string_lossy().to_string();
+            let id = photo_file_id(&file_name);
+            if id.is_none() && !is_overview_file_name(&file_name) {
+                continue;
+            }
 
             let metadata = file.metadata().map_err(|error| {
                 DScopeError::cannot_read_file(error, file.path().to_string_lossy().to_string())
@@ -181,29 +572,20 @@ impl PhotoSet {
                 DScopeError::cannot_read_file(error, file.path().to_string_lossy().to_string())
             })?;
 
-            let image = load_from_memory(&bytes)
-                .map_err(|error| DScopeError::cannot_decode_image(error, photo_file_name(id)))?;
-            let nheight = ((image.height() as f32)
-                * ((PREVIEW_WIDTH as f32) / (image.width() as f32)))
-                as u32;
-            let preview_image = image::imageops::resize(
-                &image,
-                PREVIEW_WIDTH,
-                nheight,
-                image::imageops::FilterType::Nearest,
-            );
-            let preview_color_image = ColorImage::from_rgba_unmultiplied(
-                [PREVIEW_WIDTH as usize, nheight as usize],
-                preview_image.as_bytes(),
-            );
-            let preview = RetainedImage::from_color_image(photo_file_name(id), preview_color_image);
-
-            photos.push(Photo {
-                id,
-                bytes,
-                preview,
-                info: PhotoInfo::new(time),
-            })
+            match id {
+                Some(id) => photos.push(Photo::new(
+                    id,
+                    bytes,
+                    PhotoInfo::new(time),
+                    &calibration,
+                    colour_matrix.as_ref(),
+                )?),
+                None => overviews.push(OverviewPhoto {
+                    file_name,
+                    bytes,
+                    info: Default::default(),
+                }),
+            }
         }
 
         if photos.len() == 0 {
@@ -213,22 +595,17 @@ impl PhotoSet {
         }
 
         photos.sort_by(|a, b| a.id.cmp(&b.id));
+        overviews.sort_by(|a, b| a.file_name.cmp(&b.file_name));
 
         let mut photo_set = PhotoSet {
             path,
             photos,
+            overviews,
             info: Default::default(),
+            restored_info,
         };
 
-        let mut info_path = photo_set.path.clone();
-        info_path.push(INFO_FILE_NAME);
-        if info_path.exists() {
-            let info_text = std::fs::read_to_string(&info_path).map_err(|error| {
-                DScopeError::cannot_read_file(error, info_path.to_string_lossy().to_string())
-            })?;
-            let info_data = serde_json::from_str(&info_text).map_err(|error| {
-                DScopeError::cannot_decode_info(error, info_path.to_string_lossy().to_string())
-            })?;
+        if let Some(info_data) = info_data {
             photo_set.apply_data(info_data);
         }
 
@@ -239,31 +616,47 @@ impl PhotoSet {
         Ok(photo_set)
     }
 
-    pub fn save(&self) -> DScopeResult<()> {
-        for photo in self.photos.iter() {
+    /// Returns the files already in the folder, which are not rewritten.
+    pub fn save(&self) -> DScopeResult<Vec<String>> {
+        let mut kept = Vec::new();
+        for (file_name, bytes) in self.files() {
             let mut path = self.path.clone();
-            path.push(photo_file_name(photo.id));
-            if !path.exists() {
-                std::fs::write(&path, &photo.bytes).map_err(|error| {
-                    DScopeError::cannot_write_file(error, path.to_string_lossy().to_string())
-                })?;
+            path.push(&file_name);
+            if path.exists() {
+                kept.push(file_name);
+            } else {
+                write_file_atomically(&path, bytes)?;
             }
         }
+        self.build_data()
+            .write(&self.path, self.restored_info.is_none())?;
+        Ok(kept)
+    }
 
+    pub fn verify_relocation(&self, from: PathBuf, kept: Vec<String>) -> Relocation {
         let data = serde_json::to_vec_pretty(&self.build_data()).unwrap();
-        let mut data_path = self.path.clone();
-        data_path.push(INFO_FILE_NAME);
-        std::fs::write(&data_path, data).map_err(|error| {
-            DScopeError::cannot_remove_file(error, data_path.to_string_lossy().to_string())
-        })?;
-
-        Ok(())
+        let files = self.files().chain(std::iter::once((
+            INFO_FILE_NAME.to_string(),
+            data.as_slice(),
+        )));
+        Relocation {
+            files: verify_files(files, &self.path),
+            from,
+            to: self.path.clone(),
+            kept,
+        }
     }
 
-    pub fn cleanup(&self, path: &PathBuf) -> DScopeResult<()> {
-        for photo in self.photos.iter() {
-            let mut path = path.clone();
-            path.push(photo_file_name(photo.id));
+    /// The backup of the info file is not copied, so it stays.
+    pub fn cleanup(&self, relocation: &Relocation) -> DScopeResult<()> {
+        if !relocation.is_moved() {
+            return Ok(());
+        }
+        if !relocation.is_verified() {
+            return Err(relocation.refusal());
+        }
+        for (file_name, _) in relocation.files.iter() {
+            let path = relocation.from.join(file_name);
             if path.exists() {
                 std::fs::remove_file(&path).map_err(|error| {
                     DScopeError::cannot_remove_file(error, path.to_string_lossy().to_string())
@@ -271,51 +664,454 @@ impl PhotoSet {
             }
         }
 
-        let mut data_path = path.clone();
-        data_path.push(INFO_FILE_NAME);
-        std::fs::remove_file(&data_path).map_err(|error| {
-            DScopeError::cannot_write_file(error, data_path.to_string_lossy().to_string())
-        })?;
-
         Ok(())
     }
 
+    fn files(&self) -> impl Iterator<Item = (String, &[u8])> {
+        let photos = self
+            .photos
+            .iter()
+            .map(|photo| (photo_file_name(photo.id), photo.bytes.as_slice()));
+        let overviews = self
+            .overviews
+            .iter()
+            .map(|overview| (overview.file_name.clone(), overview.bytes.as_slice()));
+        photos.chain(overviews)
+    }
+
+    pub fn add_derived_photo(
+        &mut self,
+        image: &RgbImage,
+        derivation: Derivation,
+    ) -> DScopeResult<usize> {
+        let id = self
+            .photos
+            .iter()
+            .map(|photo| photo.id + 1)
+            .max()
+            .unwrap_or(0);
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(image.clone())
+            .write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                ImageOutputFormat::Jpeg(DERIVED_PHOTO_QUALITY),
+            )
+            .map_err(|error| {
+                DScopeError::cannot_create_image(error.to_string(), photo_file_name(id))
+            })?;
+
+        let first_source = derivation
+            .sources()
+            .first()
+            .and_then(|id| self.photo_index(*id))
+            .map(|index| &self.photos[index].info);
+        let mut info = PhotoInfo::new(first_source.map_or_else(SystemTime::now, |info| info.time));
+        info.mole_id = first_source.and_then(|info| info.mole_id);
+        info.derived_from = Some(derivation);
+
+        let photo = Photo::new(
+            id,
+            bytes,
+            info,
+            &self.info.calibration,
+            self.info.colour_matrix.as_ref(),
+        )?;
+        self.photos.push(photo);
+        Ok(self.photos.len() - 1)
+    }
+
+    pub fn bursts(&self) -> Vec<Burst> {
+        let shots: Vec<BurstShot> = self
+            .photos
+            .iter()
+            .map(|photo| BurstShot {
+                hash: photo.hash,
+                time: photo.info.time,
+                sharpness: photo.quality.sharpness,
+            })
+            .collect();
+        group_bursts(&shots)
+    }
+
+    pub fn set_calibration(&mut self, calibration: CalibrationProfile) {
+        self.clear_abcd_scores();
+        let factor = self.info.calibration.px_per_mm / calibration.px_per_mm;
+        for photo in self.photos.iter_mut() {
+            photo.info.mole_metrics.scale(factor);
+            if let Some(proposal) = photo.proposal.as_mut() {
+                proposal.scale(factor);
+            }
+            if let Some(registration) = photo.info.registration.as_mut() {
+                registration.alignment.scale /= factor;
+            }
+            // Detected again on demand, as its areas are under the previous scale.
+            photo.info.change = None;
+        }
+        self.info.calibration = calibration;
+    }
+
+    pub fn set_colour_matrix(&mut self, colour_matrix: Option<ColourMatrix>) {
+        self.clear_abcd_scores();
+        self.info.colour_matrix = colour_matrix;
+    }
+
+    pub fn clear_abcd_scores(&mut self) {
+        for photo in self.photos.iter_mut() {
+            photo.info.abcd = None;
+        }
+    }
+
+    pub fn export_csv(
+        &self,
+        path: &Path,
+        mole_name: impl Fn(MoleId) -> String,
+    ) -> DScopeResult<()> {
+        let mut csv = String::from(
+            "photo,time,mole,shape,diameter,area,perimeter,major_axis,minor_axis,A,B,C,D,TDS,7PCL,area_change,colour_shift,notes\n",
+        );
+        for photo in self.photos.iter() {
+            let metrics = &photo.info.mole_metrics;
+            let measures = metrics.measures.filter(|_| metrics.size().is_some());
+            let measure = |value: Option<f32>| value.map(|value| format!("{:.3}", value));
+            let score = |value: Option<u8>| value.map(|value| value.to_string());
+            let abcd = photo.info.abcd;
+            let change = photo.info.change.map(|change| change.measures);
+            let fields = [
+                photo_file_name(photo.id),
+                DisplayTime::new(photo.info.time).to_string(),
+                photo.info.mole_id.map(&mole_name).unwrap_or_default(),
+                metrics.shape.name().to_string(),
+                measure(metrics.size()).unwrap_or_default(),
+                measure(measures.map(|m| m.area)).unwrap_or_default(),
+                measure(measures.map(|m| m.perimeter)).unwrap_or_default(),
+                measure(measures.map(|m| m.major_axis)).unwrap_or_default(),
+                measure(measures.map(|m| m.minor_axis)).unwrap_or_default(),
+                score(abcd.map(|abcd| abcd.asymmetry)).unwrap_or_default(),
+                score(abcd.map(|abcd| abcd.border)).unwrap_or_default(),
+                score(abcd.map(|abcd| abcd.colours)).unwrap_or_default(),
+                score(abcd.map(|abcd| abcd.structures)).unwrap_or_default(),
+                abcd.map(|abcd| format!("{:.2}", abcd.total()))
+                    .unwrap_or_default(),
+                photo.info.findings.score().to_string(),
+                change
+                    .map(|change| format!("{:.1}", change.area_change))
+                    .unwrap_or_default(),
+                change
+                    .map(|change| format!("{:.1}", change.colour_shift))
+                    .unwrap_or_default(),
+                photo.info.notes.clone(),
+            ];
+            let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+        std::fs::write(path, csv).map_err(|error| {
+            DScopeError::cannot_write_file(error, path.to_string_lossy().to_string())
+        })
+    }
+
     fn apply_data(&mut self, data: PhotoSetData) {
         self.info.name = data.name;
         self.info.surname = data.surname;
         self.info.time = data.time;
         self.info.notes = data.notes;
+        self.info.patient_id = data.patient_id;
+        self.info.calibration = data.calibration;
+        self.info.colour_matrix = data.colour_matrix;
         for (id, info) in data.photos {
             if let Some((index, _)) = self.photos.iter().enumerate().find(|(_, p)| p.id == id) {
                 let photo = &mut self.photos[index];
                 photo.info.time = info.time;
                 photo.info.notes = info.notes;
                 photo.info.mole_metrics = info.mole_metrics;
+                // Derived measures are recomputed so that only edits change the metrics.
+                photo.info.mole_metrics.update_measures();
+                photo.info.mole_id = info.mole_id;
+                photo.info.abcd = info.abcd;
+                photo.info.findings = info.findings;
+                photo.info.registration = info.registration;
+                photo.info.change = info.change;
+                photo.info.flagged = info.flagged;
+                photo.info.derived_from = info.derived_from;
+            }
+        }
+        for (file_name, info) in data.overviews {
+            if let Some(overview) = self
+                .overviews
+                .iter_mut()
+                .find(|overview| overview.file_name == file_name)
+            {
+                overview.info = info;
             }
         }
     }
 
     fn build_data(&self) -> PhotoSetData {
         PhotoSetData {
+            schema_version: SCHEMA_VERSION,
             name: self.info.name.clone(),
             surname: self.info.surname.clone(),
             time: self.info.time,
             notes: self.info.notes.clone(),
+            patient_id: self.info.patient_id.clone(),
+            calibration: self.info.calibration.clone(),
+            colour_matrix: self.info.colour_matrix,
             photos: self.photos.iter().fold(BTreeMap::new(), |mut map, photo| {
                 map.insert(photo.id, photo.info.clone());
                 map
             }),
+            overviews: self
+                .overviews
+                .iter()
+                .map(|overview| (overview.file_name.clone(), overview.info.clone()))
+                .collect(),
         }
     }
 }
 
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[test]
+fn test_csv_field() {
+    assert_eq!(csv_field("2.5"), "2.5");
+    assert_eq!(csv_field("itchy, \"red\""), "\"itchy, \"\"red\"\"\"");
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PhotoSetData {
+    #[serde(default)]
+    pub schema_version: u64,
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub surname: String,
     pub time: std::time::SystemTime,
+    #[serde(default)]
     pub notes: String,
+    #[serde(default)]
+    pub patient_id: Option<String>,
+    #[serde(default)]
+    pub calibration: CalibrationProfile,
+    #[serde(default)]
+    pub colour_matrix: Option<ColourMatrix>,
+    #[serde(default)]
     pub photos: BTreeMap<usize, PhotoInfo>,
+    #[serde(default)]
+    pub overviews: BTreeMap<String, OverviewInfo>,
+}
+
+impl PhotoSetData {
+    /// Falls back to the backup, unless the info file is from a newer release.
+    pub fn read(path: &Path) -> DScopeResult<Option<(Self, Option<DScopeError>)>> {
+        let info_path = path.join(INFO_FILE_NAME);
+        let backup_path = path.join(INFO_BACKUP_FILE_NAME);
+        if !info_path.exists() && !backup_path.exists() {
+            return Ok(None);
+        }
+        match Self::read_file(&info_path) {
+            Ok(data) => Ok(Some((data, None))),
+            Err(error @ DScopeError::UnsupportedInfoVersion { .. }) => Err(error),
+            Err(error) => match Self::read_file(&backup_path) {
+                Ok(data) => Ok(Some((
+                    data,
+                    Some(DScopeError::info_restored_from_backup(
+                        error,
+                        info_path.to_string_lossy().to_string(),
+                    )),
+                ))),
+                Err(_) => Err(error),
+            },
+        }
+    }
+
+    fn read_file(path: &Path) -> DScopeResult<Self> {
+        let text = std::fs::read_to_string(path).map_err(|error| {
+            DScopeError::cannot_read_file(error, path.to_string_lossy().to_string())
+        })?;
+        Self::decode(&text, path.to_string_lossy().to_string())
+    }
+
+    /// Backs up the previous info file only if it is valid and `rotate_backup`.
+    pub fn write(&self, path: &Path, rotate_backup: bool) -> DScopeResult<()> {
+        let info_path = path.join(INFO_FILE_NAME);
+        if info_path.exists() {
+            if let Ok(previous) = std::fs::read_to_string(&info_path) {
+                match Self::decode(&previous, info_path.to_string_lossy().to_string()) {
+                    Ok(_) if rotate_backup => write_file_atomically(
+                        &path.join(INFO_BACKUP_FILE_NAME),
+                        previous.as_bytes(),
+                    )?,
+                    Err(error @ DScopeError::UnsupportedInfoVersion { .. }) => return Err(error),
+                    _ => {}
+                }
+            }
+        }
+        write_file_atomically(&info_path, &serde_json::to_vec_pretty(self).unwrap())
+    }
+
+    pub fn decode(text: &str, file: String) -> DScopeResult<Self> {
+        let mut value: serde_json::Value = serde_json::from_str(text)
+            .map_err(|error| DScopeError::cannot_decode_info(error, file.clone()))?;
+        let version = value
+            .get("schema_version")
+            .and_then(|version| version.as_u64())
+            .unwrap_or(0);
+        if version > SCHEMA_VERSION {
+            return Err(DScopeError::unsupported_info_version(version, file));
+        }
+        for migration in &MIGRATIONS[version as usize..] {
+            migration(&mut value);
+        }
+        if let Some(object) = value.as_object_mut() {
+            object.insert("schema_version".to_string(), SCHEMA_VERSION.into());
+        }
+        serde_json::from_value(value).map_err(|error| DScopeError::cannot_decode_info(error, file))
+    }
+}
+
+pub fn write_file_atomically(path: &Path, bytes: &[u8]) -> DScopeResult<()> {
+    let temporary_path = temporary_path(path);
+    let write = || {
+        let mut file = std::fs::File::create(&temporary_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()
+    };
+    write().map_err(|error| {
+        let _ = std::fs::remove_file(&temporary_path);
+        DScopeError::cannot_write_file(error, temporary_path.to_string_lossy().to_string())
+    })?;
+    rename(&temporary_path, path)?;
+    // Makes the rename durable, where directories can be opened.
+    if let Some(directory) = path.parent() {
+        if let Ok(directory) = std::fs::File::open(directory) {
+            let _ = directory.sync_all();
+        }
+    }
+    Ok(())
+}
+
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(TEMPORARY_FILE_SUFFIX);
+    path.with_file_name(name)
+}
+
+fn rename(from: &Path, to: &Path) -> DScopeResult<()> {
+    std::fs::rename(from, to)
+        .map_err(|error| DScopeError::cannot_write_file(error, to.to_string_lossy().to_string()))
+}
+
+#[test]
+fn test_info_backup() {
+    let path = std::env::temp_dir().join(format!("d-scope-test-{}", std::process::id()));
+    std::fs::create_dir_all(&path).unwrap();
+    let mut data =
+        PhotoSetData::decode(include_str!("../test-data/info/v1.json"), String::new()).unwrap();
+    data.write(&path, true).unwrap();
+    data.notes = "second save".to_string();
+    data.write(&path, true).unwrap();
+    assert_eq!(
+        PhotoSetData::read(&path).unwrap().unwrap().0.notes,
+        "second save"
+    );
+    assert!(!temporary_path(&path.join(INFO_FILE_NAME)).exists());
+
+    // A truncated file falls back to the previous save, telling why, and saving the
+    // restored data keeps that backup.
+    std::fs::write(path.join(INFO_FILE_NAME), "{\"name\": \"Ann").unwrap();
+    let (restored, error) = PhotoSetData::read(&path).unwrap().unwrap();
+    assert_eq!(restored.notes, "");
+    assert!(matches!(
+        error,
+        Some(DScopeError::InfoRestoredFromBackup { .. })
+    ));
+    restored.write(&path, false).unwrap();
+    std::fs::write(path.join(INFO_FILE_NAME), "").unwrap();
+    data.write(&path, true).unwrap();
+    let backup = std::fs::read_to_string(path.join(INFO_BACKUP_FILE_NAME)).unwrap();
+    assert_eq!(
+        PhotoSetData::decode(&backup, String::new()).unwrap().notes,
+        ""
+    );
+
+    // A file of a newer release is not replaced.
+    let newer = format!("{{\"schema_version\": {}}}", SCHEMA_VERSION + 1);
+    std::fs::write(path.join(INFO_FILE_NAME), &newer).unwrap();
+    assert!(matches!(
+        PhotoSetData::read(&path),
+        Err(DScopeError::UnsupportedInfoVersion { .. })
+    ));
+    assert!(matches!(
+        data.write(&path, true),
+        Err(DScopeError::UnsupportedInfoVersion { .. })
+    ));
+    assert_eq!(
+        std::fs::read_to_string(path.join(INFO_FILE_NAME)).unwrap(),
+        newer
+    );
+
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+/// Fields added before versioning all default when missing.
+fn migrate_v0(_value: &mut serde_json::Value) {}
+
+#[test]
+fn test_decode_old_info_files() {
+    let decode = |text: &str| PhotoSetData::decode(text, "info.json".to_string()).unwrap();
+
+    let baseline = decode(include_str!("../test-data/info/v0-baseline.json"));
+    assert_eq!(baseline.schema_version, SCHEMA_VERSION);
+    assert_eq!(baseline.calibration, CalibrationProfile::default());
+    assert_eq!(baseline.photos.len(), 2);
+    assert_eq!(baseline.photos[&1].mole_metrics.size(), Some(4.2));
+    assert_eq!(baseline.photos[&1].mole_metrics.shape, MoleShape::Circle);
+    assert_eq!(baseline.photos[&2].mole_id, None);
+
+    let archive = decode(include_str!("../test-data/info/v0-archive.json"));
+    assert_eq!(archive.patient_id.as_deref(), Some("P0003"));
+    assert_eq!(archive.photos[&1].mole_id, Some(2));
+    assert_eq!(archive.photos[&1].mole_metrics.shape.name(), "Ellipse");
+
+    let full = decode(include_str!("../test-data/info/v0-full.json"));
+    assert_eq!(full.calibration.px_per_mm, 86.5);
+    assert!(full.calibration.flat_field.is_some() && full.colour_matrix.is_some());
+    let photo = &full.photos[&1];
+    assert_eq!(photo.abcd.map(|abcd| abcd.structures), Some(2));
+    assert!(photo.findings.atypical_network && !photo.findings.blue_white_veil);
+    assert!(photo.registration.is_some() && photo.change.is_some());
+    assert!(full.photos[&2].flagged);
+    assert_eq!(
+        full.photos[&3].derived_from,
+        Some(Derivation::FocusStack {
+            sources: vec![1, 2]
+        })
+    );
+    assert_eq!(full.overviews["back.jpg"].markers[0].photo_ids, vec![1, 3]);
+
+    let current = decode(include_str!("../test-data/info/v1.json"));
+    assert_eq!(current.photos[&1].mole_metrics.size(), Some(5.1));
+    assert_eq!(current.photos[&1].notes, "");
+
+    // Written back, every file decodes to the same data.
+    for data in [baseline, archive, full, current] {
+        assert_eq!(decode(&serde_json::to_string(&data).unwrap()), data);
+    }
+
+    let newer = format!(
+        r#"{{"schema_version": {}, "time": {{"secs_since_epoch": 0, "nanos_since_epoch": 0}}}}"#,
+        SCHEMA_VERSION + 1
+    );
+    assert!(matches!(
+        PhotoSetData::decode(&newer, "info.json".to_string()),
+        Err(DScopeError::UnsupportedInfoVersion { .. })
+    ));
 }
 
 pub struct DisplayTime(SystemTime);
@@ -0,0 +1,124 @@
+use image::{DynamicImage, GrayImage, Luma};
+
+use crate::{glare::glare_mask, segmentation::field_of_view};
+
+/// Width photos are reduced to before measuring their quality; blur is judged at the
+/// scale the doctor looks at a photo, not at the scale of the sensor noise.
+const QUALITY_WIDTH: u32 = 512;
+/// Lowest variance of the Laplacian of a photo in focus.
+const SHARPNESS_MIN: f32 = 30.0;
+/// Largest share of clipped pixels, in percent.
+const CLIPPED_MAX: f32 = 5.0;
+/// Largest share of glare, in percent.
+const GLARE_MAX: f32 = 5.0;
+
+/// Quality of a photo, measured inside the field of view of the scope.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhotoQuality {
+    /// Variance of the Laplacian of the luminance; low when blurred.
+    pub sharpness: f32,
+    /// Pixels black or saturated in some channel, in percent.
+    pub clipped: f32,
+    /// Pixels covered by glare, in percent.
+    pub glare: f32,
+}
+
+impl PhotoQuality {
+    pub fn measure(image: &DynamicImage) -> Self {
+        let width = QUALITY_WIDTH.min(image.width());
+        let height = ((image.height() as f32) * (width as f32) / (image.width() as f32)) as u32;
+        let small = image.thumbnail_exact(width, height.max(1)).to_rgb8();
+        let (width, height) = small.dimensions();
+        let view = field_of_view(width, height);
+        let glare = glare_mask(&small);
+        let luminance = GrayImage::from_fn(width, height, |x, y| {
+            let pixel = small.get_pixel(x, y);
+            Luma([((pixel[0] as u32 * 3 + pixel[1] as u32 * 6 + pixel[2] as u32) / 10) as u8])
+        });
+
+        let (mut count, mut clipped, mut glared) = (0usize, 0usize, 0usize);
+        let (mut sum, mut sum_squares, mut laplacians) = (0.0f64, 0.0f64, 0usize);
+        for y in 0..height {
+            for x in 0..width {
+                if !view.get(x, y) {
+                    continue;
+                }
+                count += 1;
+                let pixel = small.get_pixel(x, y);
+                if pixel.0.contains(&255) || pixel.0 == [0, 0, 0] {
+                    clipped += 1;
+                }
+                if glare.get(x, y) {
+                    glared += 1;
+                }
+                if x == 0 || y == 0 || x + 1 == width || y + 1 == height {
+                    continue;
+                }
+                let at = |x: u32, y: u32| luminance.get_pixel(x, y)[0] as f64;
+                let laplacian =
+                    at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4.0 * at(x, y);
+                sum += laplacian;
+                sum_squares += laplacian * laplacian;
+                laplacians += 1;
+            }
+        }
+
+        let percent = |value: usize| 100.0 * value as f32 / count.max(1) as f32;
+        let sharpness = if laplacians > 0 {
+            let mean = sum / laplacians as f64;
+            (sum_squares / laplacians as f64 - mean * mean) as f32
+        } else {
+            0.0
+        };
+        Self {
+            sharpness,
+            clipped: percent(clipped),
+            glare: percent(glared),
+        }
+    }
+
+    /// What is wrong with the photo, if anything.
+    pub fn issues(&self) -> Vec<&'static str> {
+        let mut issues = Vec::new();
+        if self.sharpness < SHARPNESS_MIN {
+            issues.push("blurred");
+        }
+        if self.clipped > CLIPPED_MAX {
+            issues.push("clipped");
+        }
+        if self.glare > GLARE_MAX {
+            issues.push("glare");
+        }
+        issues
+    }
+
+    pub fn is_poor(&self) -> bool {
+        !self.issues().is_empty()
+    }
+}
+
+#[test]
+fn test_photo_quality() {
+    // Skin with a fine pseudo-random texture.
+    let mut state = 12345u32;
+    let sharp = image::RgbImage::from_fn(256, 192, |_, _| {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        let noise = ((state >> 16) % 40) as u8;
+        image::Rgb([180 + noise, 140 + noise, 120 + noise])
+    });
+    let quality = PhotoQuality::measure(&DynamicImage::ImageRgb8(sharp.clone()));
+    assert!(!quality.is_poor(), "{:?}", quality);
+
+    let blurred = image::imageops::blur(&sharp, 3.0);
+    let quality = PhotoQuality::measure(&DynamicImage::ImageRgb8(blurred));
+    assert_eq!(quality.issues(), vec!["blurred"]);
+
+    let mut glared = sharp;
+    for y in 60..130 {
+        for x in 90..170 {
+            glared.put_pixel(x, y, image::Rgb([255, 255, 255]));
+        }
+    }
+    let quality = PhotoQuality::measure(&DynamicImage::ImageRgb8(glared));
+    assert_eq!(quality.issues(), vec!["clipped", "glare"]);
+}
@@ -0,0 +1,393 @@
+use image::{DynamicImage, GrayImage, Luma, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, time::SystemTime};
+
+use crate::{
+    correction::sample,
+    imaging::{Mask, Moments},
+    outline::Point,
+    segmentation::{cleaned_segmentation_image, field_of_view, lesion_mask},
+};
+
+const SAMPLE_STEP: usize = 2;
+const TRANSLATION_STEP: f32 = 0.25;
+const ROTATION_STEP: f32 = 0.1;
+const SCALE_STEP: f32 = 0.04;
+const REFINEMENT_ROUNDS: usize = 6;
+const MAX_MOVES: usize = 20;
+const MIN_OVERLAP: f32 = 0.25;
+const MIN_CORRELATION: f32 = 0.3;
+const OVERLAP_SEARCH_STEP: f32 = 1.0;
+
+/// `p -> scale * R(rotation) * p + translation`, in millimetres.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Alignment {
+    pub scale: f32,
+    pub rotation: f32,
+    pub translation: Point,
+}
+
+impl Default for Alignment {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            rotation: 0.0,
+            translation: [0.0, 0.0],
+        }
+    }
+}
+
+impl Alignment {
+    fn around(scale: f32, rotation: f32, from: Point, to: Point) -> Self {
+        let moved = Self {
+            scale,
+            rotation,
+            translation: [0.0, 0.0],
+        }
+        .apply(from);
+        Self {
+            scale,
+            rotation,
+            translation: [to[0] - moved[0], to[1] - moved[1]],
+        }
+    }
+
+    pub fn apply(&self, point: Point) -> Point {
+        let (sin, cos) = self.rotation.sin_cos();
+        [
+            self.scale * (cos * point[0] - sin * point[1]) + self.translation[0],
+            self.scale * (sin * point[0] + cos * point[1]) + self.translation[1],
+        ]
+    }
+
+    pub fn inverse(&self) -> Self {
+        let scale = 1.0 / self.scale;
+        let rotation = -self.rotation;
+        let moved = Self {
+            scale,
+            rotation,
+            translation: [0.0, 0.0],
+        }
+        .apply(self.translation);
+        Self {
+            scale,
+            rotation,
+            translation: [-moved[0], -moved[1]],
+        }
+    }
+
+    pub fn after(&self, first: &Alignment) -> Self {
+        Self {
+            scale: self.scale * first.scale,
+            rotation: self.rotation + first.rotation,
+            translation: self.apply(first.translation),
+        }
+    }
+
+    fn nudged(&self, parameter: usize, step: f32) -> Self {
+        let mut result = *self;
+        match parameter {
+            0 => result.translation[0] += step,
+            1 => result.translation[1] += step,
+            2 => result.rotation += step,
+            _ => result.scale *= 1.0 + step,
+        }
+        result
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Registration {
+    /// Time and id identify the baseline even after its visit was archived.
+    pub baseline_time: SystemTime,
+    pub baseline_photo_id: usize,
+    pub alignment: Alignment,
+    pub correlation: f32,
+}
+
+impl Registration {
+    pub fn is_to(&self, photo_id: usize, time: SystemTime) -> bool {
+        self.baseline_photo_id == photo_id && self.baseline_time == time
+    }
+}
+
+struct Frame {
+    luminance: GrayImage,
+    view: Mask,
+    px_per_mm: f32,
+    lesion: Option<Moments>,
+}
+
+impl Frame {
+    fn new(image: &RgbImage, px_per_mm: f32) -> Self {
+        let small = cleaned_segmentation_image(&DynamicImage::ImageRgb8(image.clone()));
+        let (width, height) = small.dimensions();
+        let luminance = image::imageops::blur(
+            &GrayImage::from_fn(width, height, |x, y| {
+                let pixel = small.get_pixel(x, y);
+                Luma([((pixel[0] as u32 * 3 + pixel[1] as u32 * 6 + pixel[2] as u32) / 10) as u8])
+            }),
+            1.0,
+        );
+        Self {
+            luminance,
+            view: field_of_view(width, height),
+            px_per_mm: px_per_mm * width as f32 / image.width() as f32,
+            lesion: lesion_mask(&small).and_then(|lesion| Moments::new(&lesion)),
+        }
+    }
+
+    fn to_mm(&self, x: f32, y: f32) -> Point {
+        [
+            (x - self.luminance.width() as f32 / 2.0) / self.px_per_mm,
+            (self.luminance.height() as f32 / 2.0 - y) / self.px_per_mm,
+        ]
+    }
+
+    fn to_pixel(&self, point: Point) -> [f32; 2] {
+        [
+            point[0] * self.px_per_mm + self.luminance.width() as f32 / 2.0,
+            self.luminance.height() as f32 / 2.0 - point[1] * self.px_per_mm,
+        ]
+    }
+
+    fn sample(&self, point: Point) -> Option<f32> {
+        let [x, y] = self.to_pixel(point);
+        if x < 0.0 || y < 0.0 || !self.view.get_signed(x as i64, y as i64) {
+            return None;
+        }
+        let (x, y) = ((x - 0.5).max(0.0), (y - 0.5).max(0.0));
+        let (width, height) = self.luminance.dimensions();
+        let (x0, y0) = ((x as u32).min(width - 1), (y as u32).min(height - 1));
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let at = |x: u32, y: u32| self.luminance.get_pixel(x, y)[0] as f32;
+        let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+        let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+        Some(top * (1.0 - fy) + bottom * fy)
+    }
+
+    fn lesion(&self) -> Option<(Point, f32, f32)> {
+        self.lesion.as_ref().map(|moments| {
+            (
+                self.to_mm(moments.cx, moments.cy),
+                // The moments are in pixels, with the Y axis downwards.
+                -moments.angle,
+                moments.area / (self.px_per_mm * self.px_per_mm),
+            )
+        })
+    }
+}
+
+/// Returns the alignment from the millimetres of `photo` to those of `baseline`.
+pub fn register(
+    baseline: &RgbImage,
+    baseline_px_per_mm: f32,
+    photo: &RgbImage,
+    photo_px_per_mm: f32,
+) -> Option<(Alignment, f32)> {
+    let baseline = Frame::new(baseline, baseline_px_per_mm);
+    let photo = Frame::new(photo, photo_px_per_mm);
+
+    // Successive shots are often aligned already.
+    let mut guesses = vec![Alignment::default()];
+    if let (Some((to, baseline_angle, baseline_area)), Some((from, angle, area))) =
+        (baseline.lesion(), photo.lesion())
+    {
+        let scale = (baseline_area / area).sqrt();
+        // Round lesions have no reliable orientation: try no rotation too.
+        guesses.extend(
+            [baseline_angle - angle, baseline_angle - angle + PI, 0.0]
+                .iter()
+                .map(|rotation| Alignment::around(scale, *rotation, from, to)),
+        );
+    }
+    best_match(&baseline, &photo, guesses)
+}
+
+/// Tries a grid of translations first, so the photos must not be rotated much.
+pub fn register_overlapping(
+    baseline: &RgbImage,
+    baseline_px_per_mm: f32,
+    photo: &RgbImage,
+    photo_px_per_mm: f32,
+) -> Option<(Alignment, f32)> {
+    let baseline = Frame::new(baseline, baseline_px_per_mm);
+    let photo = Frame::new(photo, photo_px_per_mm);
+
+    let reach = |size: fn(&GrayImage) -> u32| {
+        let reach = (size(&baseline.luminance) as f32 / baseline.px_per_mm
+            + size(&photo.luminance) as f32 / photo.px_per_mm)
+            / 2.0;
+        (reach / OVERLAP_SEARCH_STEP) as i32
+    };
+    let (reach_x, reach_y) = (reach(GrayImage::width), reach(GrayImage::height));
+    let guesses = (-reach_y..=reach_y)
+        .flat_map(|y| (-reach_x..=reach_x).map(move |x| (x, y)))
+        .map(|(x, y)| Alignment {
+            translation: [
+                x as f32 * OVERLAP_SEARCH_STEP,
+                y as f32 * OVERLAP_SEARCH_STEP,
+            ],
+            ..Default::default()
+        })
+        .collect();
+    best_match(&baseline, &photo, guesses)
+}
+
+fn best_match(
+    baseline: &Frame,
+    photo: &Frame,
+    guesses: Vec<Alignment>,
+) -> Option<(Alignment, f32)> {
+    let start = guesses
+        .into_iter()
+        .map(|guess| (guess, correlation(baseline, photo, &guess)))
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())?;
+    let (alignment, correlation) = refine(baseline, photo, start);
+    Some((alignment, correlation)).filter(|_| correlation >= MIN_CORRELATION)
+}
+
+fn refine(baseline: &Frame, photo: &Frame, start: (Alignment, f32)) -> (Alignment, f32) {
+    let (mut best, mut best_correlation) = start;
+    let mut steps = [
+        TRANSLATION_STEP,
+        TRANSLATION_STEP,
+        ROTATION_STEP,
+        SCALE_STEP,
+    ];
+    for _ in 0..REFINEMENT_ROUNDS {
+        for _ in 0..MAX_MOVES {
+            let mut improved = false;
+            for (parameter, step) in steps.iter().enumerate() {
+                for sign in [-1.0, 1.0] {
+                    let candidate = best.nudged(parameter, sign * step);
+                    let candidate_correlation = correlation(baseline, photo, &candidate);
+                    if candidate_correlation > best_correlation {
+                        best = candidate;
+                        best_correlation = candidate_correlation;
+                        improved = true;
+                    }
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+        for step in steps.iter_mut() {
+            *step /= 2.0;
+        }
+    }
+    (best, best_correlation)
+}
+
+fn correlation(baseline: &Frame, photo: &Frame, alignment: &Alignment) -> f32 {
+    let (width, height) = photo.luminance.dimensions();
+    let (mut n, mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+        (0usize, 0.0f64, 0.0f64, 0.0f64, 0.0f64, 0.0f64);
+    let mut total = 0;
+    for y in (0..height).step_by(SAMPLE_STEP) {
+        for x in (0..width).step_by(SAMPLE_STEP) {
+            if !photo.view.get(x, y) {
+                continue;
+            }
+            total += 1;
+            let point = alignment.apply(photo.to_mm(x as f32 + 0.5, y as f32 + 0.5));
+            if let Some(b) = baseline.sample(point) {
+                let a = photo.luminance.get_pixel(x, y)[0] as f64;
+                let b = b as f64;
+                n += 1;
+                sum_a += a;
+                sum_b += b;
+                sum_aa += a * a;
+                sum_bb += b * b;
+                sum_ab += a * b;
+            }
+        }
+    }
+    if n == 0 || (n as f32) < total as f32 * MIN_OVERLAP {
+        return -1.0;
+    }
+    let n = n as f64;
+    let covariance = sum_ab - sum_a * sum_b / n;
+    let variance = (sum_aa - sum_a * sum_a / n) * (sum_bb - sum_b * sum_b / n);
+    if variance <= 0.0 {
+        return -1.0;
+    }
+    (covariance / variance.sqrt()) as f32
+}
+
+/// `alignment` maps the millimetres of the output to those of `image`.
+pub fn warp(
+    image: &RgbImage,
+    px_per_mm: f32,
+    alignment: &Alignment,
+    target_px_per_mm: f32,
+    (width, height): (u32, u32),
+) -> RgbImage {
+    let half_width = image.width() as f32 / 2.0;
+    let half_height = image.height() as f32 / 2.0;
+    RgbImage::from_fn(width, height, |x, y| {
+        let point = alignment.apply([
+            (x as f32 + 0.5 - width as f32 / 2.0) / target_px_per_mm,
+            (height as f32 / 2.0 - y as f32 - 0.5) / target_px_per_mm,
+        ]);
+        sample(
+            image,
+            point[0] * px_per_mm + half_width - 0.5,
+            half_height - point[1] * px_per_mm - 0.5,
+        )
+    })
+}
+
+#[test]
+fn test_alignment_inverse() {
+    let alignment = Alignment {
+        scale: 1.2,
+        rotation: 0.3,
+        translation: [1.0, -2.0],
+    };
+    let point = alignment.inverse().apply(alignment.apply([0.5, 0.7]));
+    assert!((point[0] - 0.5).abs() < 1e-5 && (point[1] - 0.7).abs() < 1e-5);
+    let point = alignment.inverse().after(&alignment).apply([0.5, 0.7]);
+    assert!((point[0] - 0.5).abs() < 1e-5 && (point[1] - 0.7).abs() < 1e-5);
+}
+
+#[test]
+fn test_register() {
+    // An elliptic lesion with a dot beside it, so that the orientation is not ambiguous;
+    // `alignment` maps the millimetres of the photo to those of the scene.
+    let shot = |alignment: &Alignment| {
+        RgbImage::from_fn(256, 192, |x, y| {
+            let p = alignment.apply([
+                (x as f32 + 0.5 - 128.0) / 20.0,
+                (96.0 - y as f32 - 0.5) / 20.0,
+            ]);
+            let in_ellipse = ((p[0] - 0.5) / 2.0).powi(2) + (p[1] / 1.0).powi(2) <= 1.0;
+            let in_dot = (p[0] - 1.5).powi(2) + (p[1] - 1.6).powi(2) <= 0.16;
+            if in_ellipse || in_dot {
+                image::Rgb([90, 60, 40])
+            } else {
+                image::Rgb([220, 180, 160])
+            }
+        })
+    };
+    let baseline = shot(&Alignment::default());
+    let expected = Alignment {
+        scale: 1.05,
+        rotation: 0.35,
+        translation: [0.4, -0.3],
+    };
+    let photo = shot(&expected);
+    let (alignment, correlation) = register(&baseline, 20.0, &photo, 20.0).unwrap();
+    assert!(correlation > 0.9, "{}", correlation);
+    for point in [[0.0, 0.0], [2.0, 1.0], [-1.0, 2.0]] {
+        let (found, wanted) = (alignment.apply(point), expected.apply(point));
+        assert!(
+            (found[0] - wanted[0]).hypot(found[1] - wanted[1]) < 0.1,
+            "{:?} {:?}",
+            alignment,
+            expected
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+
+use crate::errors::DScopeError;
+
+/// How the copy of a file of a visit compares with the file itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileCheck {
+    Verified,
+    Missing,
+    Unreadable,
+    SizeMismatch { expected: u64, found: u64 },
+    ContentMismatch,
+}
+
+impl std::fmt::Display for FileCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileCheck::Verified => f.write_str("verified"),
+            FileCheck::Missing => f.write_str("missing"),
+            FileCheck::Unreadable => f.write_str("unreadable"),
+            FileCheck::SizeMismatch { expected, found } => {
+                f.write_fmt(format_args!("{} bytes instead of {}", found, expected))
+            }
+            FileCheck::ContentMismatch => f.write_str("different content"),
+        }
+    }
+}
+
+/// A visit saved into another folder, with the check of each of its files there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub files: Vec<(String, FileCheck)>,
+    /// Files that were already in the new folder and were not rewritten.
+    pub kept: Vec<String>,
+}
+
+impl Relocation {
+    /// Whether the visit was saved into another folder, leaving originals to clean up.
+    pub fn is_moved(&self) -> bool {
+        !same_folder(&self.from, &self.to)
+    }
+
+    /// Whether all the files were verified, so that the originals can go.
+    pub fn is_verified(&self) -> bool {
+        self.files
+            .iter()
+            .all(|(_, check)| *check == FileCheck::Verified)
+    }
+
+    /// One line per file, the mismatches first.
+    pub fn summary(&self) -> String {
+        let mut files: Vec<_> = self.files.iter().collect();
+        files.sort_by_key(|(_, check)| *check == FileCheck::Verified);
+        files
+            .iter()
+            .map(|(file_name, check)| {
+                if self.kept.contains(file_name) {
+                    format!("{}: {}, already there and not rewritten", file_name, check)
+                } else {
+                    format!("{}: {}", file_name, check)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The error refusing to remove the originals, with the summary.
+    pub fn refusal(&self) -> DScopeError {
+        DScopeError::unverified_copy(self.from.to_string_lossy().to_string(), self.summary())
+    }
+}
+
+fn same_folder(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Checks the copies in `path` of files given with their expected content, by size, then
+/// byte by byte.
+pub fn verify_files<'a>(
+    files: impl Iterator<Item = (String, &'a [u8])>,
+    path: &Path,
+) -> Vec<(String, FileCheck)> {
+    files
+        .map(|(file_name, bytes)| {
+            let check = verify_file(&path.join(&file_name), bytes);
+            (file_name, check)
+        })
+        .collect()
+}
+
+fn verify_file(path: &Path, bytes: &[u8]) -> FileCheck {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return FileCheck::Missing,
+    };
+    if metadata.len() != bytes.len() as u64 {
+        return FileCheck::SizeMismatch {
+            expected: bytes.len() as u64,
+            found: metadata.len(),
+        };
+    }
+    match std::fs::read(path) {
+        Ok(copy) if copy == bytes => FileCheck::Verified,
+        Ok(_) => FileCheck::ContentMismatch,
+        Err(_) => FileCheck::Unreadable,
+    }
+}
+
+#[test]
+fn test_verify_files() {
+    let path = std::env::temp_dir().join(format!("d-scope-relocation-{}", std::process::id()));
+    std::fs::create_dir_all(&path).unwrap();
+    std::fs::write(path.join("PICT0001.jpg"), b"first photo").unwrap();
+    std::fs::write(path.join("PICT0002.jpg"), b"second").unwrap();
+    std::fs::write(path.join("PICT0003.jpg"), b"third photo").unwrap();
+
+    let files = [
+        ("PICT0001.jpg", &b"first photo"[..]),
+        ("PICT0002.jpg", &b"second photo"[..]),
+        ("PICT0003.jpg", &b"third_photo"[..]),
+        ("PICT0004.jpg", &b"fourth photo"[..]),
+    ];
+    let checks = verify_files(
+        files
+            .iter()
+            .map(|(file_name, bytes)| (file_name.to_string(), *bytes)),
+        &path,
+    );
+    let relocation = Relocation {
+        from: std::env::temp_dir(),
+        to: path.clone(),
+        files: checks,
+        kept: vec!["PICT0003.jpg".to_string()],
+    };
+    std::fs::remove_dir_all(&path).unwrap();
+
+    let checks: Vec<FileCheck> = relocation.files.iter().map(|(_, check)| *check).collect();
+    assert_eq!(
+        checks,
+        vec![
+            FileCheck::Verified,
+            FileCheck::SizeMismatch {
+                expected: 12,
+                found: 6
+            },
+            FileCheck::ContentMismatch,
+            FileCheck::Missing,
+        ]
+    );
+    assert!(relocation.is_moved() && !relocation.is_verified());
+    assert!(relocation.summary().ends_with("PICT0001.jpg: verified"));
+    assert!(relocation
+        .summary()
+        .contains("PICT0003.jpg: different content, already there and not rewritten"));
+
+    let moved = Relocation {
+        files: vec![("PICT0001.jpg".to_string(), FileCheck::Verified)],
+        ..relocation.clone()
+    };
+    assert!(moved.is_verified());
+    let same_folder = Relocation {
+        to: relocation.from.clone(),
+        ..moved
+    };
+    assert!(!same_folder.is_moved());
+}
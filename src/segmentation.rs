@@ -0,0 +1,198 @@
+use image::{DynamicImage, GrayImage, RgbImage};
+
+use crate::{
+    glare::glare_mask,
+    hair::{inpaint, remove_hair},
+    imaging::{otsu_threshold, simplify_polygon, Mask},
+    outline::MoleShape,
+    photo_set::MoleMetrics,
+};
+
+/// Width the photos are reduced to before segmenting them.
+const SEGMENTATION_WIDTH: u32 = 256;
+/// Fraction of the inscribed ellipse considered inside the scope field of view; the
+/// vignetted rim of the adapter tube would otherwise look like a huge dark lesion.
+const FIELD_OF_VIEW: f32 = 0.9;
+/// Smallest lesion, as a fraction of the field of view, worth proposing.
+const MIN_LESION_FRACTION: f32 = 0.002;
+/// Tolerance of the outline simplification, in segmentation pixels.
+const OUTLINE_TOLERANCE: f32 = 1.0;
+/// Points sampled along circles and ellipses when rasterising them.
+const OUTLINE_SAMPLES: usize = 64;
+
+/// Reduces a photo to the working size of the segmentation.
+pub fn segmentation_image(image: &DynamicImage) -> RgbImage {
+    let width = SEGMENTATION_WIDTH.min(image.width());
+    let height = ((image.height() as f32) * (width as f32) / (image.width() as f32)) as u32;
+    image.thumbnail_exact(width, height.max(1)).to_rgb8()
+}
+
+/// Reduces a photo like `segmentation_image`, then fills in glare and removes hair.
+pub fn cleaned_segmentation_image(image: &DynamicImage) -> RgbImage {
+    let mut small = segmentation_image(image);
+    let glare = glare_mask(&small);
+    inpaint(&mut small, &glare);
+    remove_hair(&small)
+}
+
+/// Proposes the outline of the lesion in a photo.
+///
+/// The blue channel, where pigmented lesions contrast most with the skin, is thresholded
+/// with Otsu's method inside the field of view; the mask is cleaned up with an opening and
+/// a closing, and the largest component not touching the edge of the field of view is
+/// taken as the lesion. Glare is filled in and hair removed first, as they would otherwise
+/// split the lesion or connect it to the edge. `px_per_mm` is the scale of the full size
+/// photo.
+pub fn segment_lesion(image: &DynamicImage, px_per_mm: f32) -> Option<MoleMetrics> {
+    let small = cleaned_segmentation_image(image);
+    let scale = px_per_mm * small.width() as f32 / image.width() as f32;
+    let lesion = lesion_mask(&small)?;
+    Some(mask_metrics(&lesion, scale))
+}
+
+/// Mask of the lesion in a photo already reduced by `segmentation_image`.
+pub fn lesion_mask(image: &RgbImage) -> Option<Mask> {
+    let (width, height) = image.dimensions();
+    let channel = image::imageops::blur(
+        &GrayImage::from_fn(width, height, |x, y| {
+            image::Luma([image.get_pixel(x, y)[2]])
+        }),
+        1.0,
+    );
+
+    let view = field_of_view(width, height);
+    let threshold = otsu_threshold(
+        channel
+            .enumerate_pixels()
+            .filter(|(x, y, _)| view.get(*x, *y))
+            .map(|(_, _, pixel)| pixel[0]),
+    );
+    let dark = Mask::from_fn(width, height, |x, y| {
+        view.get(x, y) && channel.get_pixel(x, y)[0] <= threshold
+    });
+    let cleaned = dark.open(1).close(2);
+
+    let rim = Mask::from_fn(width, height, |x, y| !view.get(x, y)).dilate(1);
+    let min_area = (view.count() as f32 * MIN_LESION_FRACTION) as usize;
+    let lesion = cleaned
+        .components()
+        .into_iter()
+        .filter(|component| component.area() >= min_area && !component.touches(&rim))
+        .max_by_key(|component| component.area())?;
+    Some(lesion.to_mask(width, height).fill_holes())
+}
+
+/// Outline metrics of a lesion mask, in millimetres around the center of the image.
+pub fn mask_metrics(lesion: &Mask, px_per_mm: f32) -> MoleMetrics {
+    let half_width = lesion.width as f32 / 2.0;
+    let half_height = lesion.height as f32 / 2.0;
+    let contour: Vec<[f32; 2]> = lesion
+        .contour()
+        .iter()
+        .map(|corner| [corner[0] as f32, corner[1] as f32])
+        .collect();
+    let points = simplify_polygon(&contour, OUTLINE_TOLERANCE)
+        .iter()
+        .map(|point| {
+            [
+                (point[0] - half_width) / px_per_mm,
+                (half_height - point[1]) / px_per_mm,
+            ]
+        })
+        .collect();
+
+    let mut metrics = MoleMetrics {
+        shape: MoleShape::Outline {
+            points,
+            spline: false,
+        },
+        ..Default::default()
+    };
+    metrics.update_measures();
+    metrics
+}
+
+/// Rasterises the outline of `metrics` on an image of the given size; the inverse of
+/// `mask_metrics`.
+pub fn metrics_mask(metrics: &MoleMetrics, width: u32, height: u32, px_per_mm: f32) -> Mask {
+    let half_width = width as f32 / 2.0;
+    let half_height = height as f32 / 2.0;
+    let polygon: Vec<[f32; 2]> = metrics
+        .outline_points(OUTLINE_SAMPLES)
+        .iter()
+        .map(|point| {
+            [
+                point[0] * px_per_mm + half_width,
+                half_height - point[1] * px_per_mm,
+            ]
+        })
+        .collect();
+    Mask::from_polygon(width, height, &polygon)
+}
+
+/// Part of a photo of the given size inside the field of view of the scope.
+pub fn field_of_view(width: u32, height: u32) -> Mask {
+    let rx = width as f32 / 2.0 * FIELD_OF_VIEW;
+    let ry = height as f32 / 2.0 * FIELD_OF_VIEW;
+    Mask::from_fn(width, height, |x, y| {
+        let dx = (x as f32 + 0.5 - width as f32 / 2.0) / rx;
+        let dy = (y as f32 + 0.5 - height as f32 / 2.0) / ry;
+        dx * dx + dy * dy <= 1.0
+    })
+}
+
+#[test]
+fn test_segment_lesion() {
+    let image =
+        DynamicImage::ImageRgb8(crate::fixtures::lesion_image(256, 192, (148.0, 96.0), 30.0));
+    let metrics = segment_lesion(&image, 10.0).unwrap();
+    let area = metrics.area().unwrap();
+    let expected = std::f32::consts::PI * 3.0 * 3.0;
+    assert!((area - expected).abs() / expected < 0.05, "area {}", area);
+    assert!((metrics.center_x - 2.0).abs() < 0.1);
+    assert!(metrics.center_y.abs() < 0.1);
+}
+
+#[test]
+fn test_segment_lesion_with_hair() {
+    let mut image = crate::fixtures::lesion_image(256, 192, (128.0, 96.0), 30.0);
+    for x in 0..256 {
+        for y in 90..92 {
+            image.put_pixel(x, y, image::Rgb([20, 15, 15]));
+        }
+    }
+    let metrics = segment_lesion(&DynamicImage::ImageRgb8(image), 10.0).unwrap();
+    let area = metrics.area().unwrap();
+    let expected = std::f32::consts::PI * 3.0 * 3.0;
+    assert!((area - expected).abs() / expected < 0.05, "area {}", area);
+}
+
+#[test]
+fn test_segment_lesion_with_glare() {
+    let mut image = crate::fixtures::lesion_image(256, 192, (128.0, 96.0), 30.0);
+    // A reflection of the contact plate in the middle of the lesion.
+    for x in 120..136 {
+        for y in 88..104 {
+            image.put_pixel(x, y, image::Rgb([250, 250, 250]));
+        }
+    }
+    let metrics = segment_lesion(&DynamicImage::ImageRgb8(image), 10.0).unwrap();
+    let area = metrics.area().unwrap();
+    let expected = std::f32::consts::PI * 3.0 * 3.0;
+    assert!((area - expected).abs() / expected < 0.05, "area {}", area);
+}
+
+#[test]
+fn test_metrics_mask_round_trip() {
+    let lesion = Mask::from_fn(64, 48, |x, y| {
+        (10..30).contains(&x) && (20..40).contains(&y)
+    });
+    let metrics = mask_metrics(&lesion, 4.0);
+    assert_eq!(metrics_mask(&metrics, 64, 48, 4.0), lesion);
+}
+
+#[test]
+fn test_segment_nothing() {
+    let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 64, image::Rgb([200, 170, 150])));
+    assert_eq!(segment_lesion(&image, 10.0), None);
+}
@@ -0,0 +1,98 @@
+use image::{ImageBuffer, Luma, RgbImage};
+
+use crate::registration::{register, warp};
+
+/// Blur of the focus measure, in pixels; the sharpest photo is picked area by area
+/// rather than pixel by pixel, where noise would decide.
+const FOCUS_SMOOTHING: f32 = 4.0;
+
+type FocusMap = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+/// Merges photos of the same spot focused at different depths, of scale `px_per_mm`.
+///
+/// The photos are aligned to the first one, as the scope moves a little between shots,
+/// and each pixel is taken from the photo where the smoothed absolute Laplacian of the
+/// luminance, a local measure of focus, is highest. Photos that can be neither aligned
+/// nor used as they are, being of another size, are left out.
+pub fn focus_stack(images: &[RgbImage], px_per_mm: f32) -> Option<RgbImage> {
+    let (base, others) = images.split_first()?;
+    let mut aligned = vec![base.clone()];
+    for image in others {
+        match register(base, px_per_mm, image, px_per_mm) {
+            Some((alignment, _)) => aligned.push(warp(
+                image,
+                px_per_mm,
+                &alignment.inverse(),
+                px_per_mm,
+                base.dimensions(),
+            )),
+            None if image.dimensions() == base.dimensions() => aligned.push(image.clone()),
+            None => {}
+        }
+    }
+
+    let focus: Vec<FocusMap> = aligned.iter().map(focus_measure).collect();
+    let (width, height) = base.dimensions();
+    Some(RgbImage::from_fn(width, height, |x, y| {
+        let sharpest = (0..aligned.len())
+            .max_by(|a, b| focus[*a].get_pixel(x, y)[0].total_cmp(&focus[*b].get_pixel(x, y)[0]))
+            .unwrap();
+        *aligned[sharpest].get_pixel(x, y)
+    }))
+}
+
+fn focus_measure(image: &RgbImage) -> FocusMap {
+    let (width, height) = image.dimensions();
+    let luminance = |x: u32, y: u32| {
+        let pixel = image.get_pixel(x, y);
+        (pixel[0] as f32 * 3.0 + pixel[1] as f32 * 6.0 + pixel[2] as f32) / 2550.0
+    };
+    // Blurring clamps floating point pixels to [0, 1], which bounds the absolute
+    // Laplacian of the luminance once divided by 4.
+    let laplacian = FocusMap::from_fn(width, height, |x, y| {
+        let neighbours = luminance(x.saturating_sub(1), y)
+            + luminance((x + 1).min(width - 1), y)
+            + luminance(x, y.saturating_sub(1))
+            + luminance(x, (y + 1).min(height - 1));
+        Luma([(neighbours - 4.0 * luminance(x, y)).abs() / 4.0])
+    });
+    image::imageops::blur(&laplacian, FOCUS_SMOOTHING)
+}
+
+#[test]
+fn test_focus_stack() {
+    let mut state = 54321u32;
+    let sharp = RgbImage::from_fn(128, 96, |_, _| {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        let noise = ((state >> 16) % 60) as u8;
+        image::Rgb([160 + noise, 120 + noise, 100 + noise])
+    });
+    let blurred = image::imageops::blur(&sharp, 2.0);
+    // Each shot is in focus on one half only.
+    let half = |left_sharp: bool| {
+        RgbImage::from_fn(128, 96, |x, y| {
+            if (x < 64) == left_sharp {
+                *sharp.get_pixel(x, y)
+            } else {
+                *blurred.get_pixel(x, y)
+            }
+        })
+    };
+    let error = |image: &RgbImage| {
+        image
+            .pixels()
+            .zip(sharp.pixels())
+            .map(|(a, b)| (a[1] as f32 - b[1] as f32).abs())
+            .sum::<f32>()
+            / (128.0 * 96.0)
+    };
+
+    let shots = [half(true), half(false)];
+    let stacked = focus_stack(&shots, 10.0).unwrap();
+    assert!(
+        error(&stacked) < 0.5 * error(&shots[0]),
+        "{} {}",
+        error(&stacked),
+        error(&shots[0])
+    );
+}
@@ -0,0 +1,118 @@
+use std::time::SystemTime;
+
+use crate::{mole::MoleObservation, photo_set::MoleMetrics};
+
+pub const DEFAULT_GROWTH_ALERT: f32 = 0.1;
+pub const GROWTH_ALERT_MAX: f32 = 1.0;
+
+const SECONDS_PER_MONTH: f64 = 30.44 * 24.0 * 60.0 * 60.0;
+
+/// Signed distance in months from `from` to `to`.
+pub fn months_between(from: SystemTime, to: SystemTime) -> f64 {
+    match to.duration_since(from) {
+        Ok(elapsed) => elapsed.as_secs_f64() / SECONDS_PER_MONTH,
+        Err(error) => -error.duration().as_secs_f64() / SECONDS_PER_MONTH,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimelineMeasure {
+    Diameter,
+    Area,
+}
+
+impl TimelineMeasure {
+    pub fn value(&self, metrics: &MoleMetrics) -> Option<f32> {
+        match self {
+            TimelineMeasure::Diameter => metrics.size(),
+            TimelineMeasure::Area => metrics.area(),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TimelineMeasure::Diameter => "Diameter (mm)",
+            TimelineMeasure::Area => "Area (mm²)",
+        }
+    }
+}
+
+/// A measured observation placed on the timeline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimelinePoint {
+    /// Index of the observation in the mole history.
+    pub observation_index: usize,
+    /// Months since the first observation of the history.
+    pub months: f64,
+    pub value: f32,
+    /// Growth since the previous measured point, in units per month.
+    pub rate: Option<f32>,
+}
+
+/// Places every observation for which `measure` gives a value on the timeline.
+///
+/// Observations are expected in chronological order, as returned by the mole history.
+pub fn timeline_points(
+    observations: &[MoleObservation],
+    measure: impl Fn(&MoleObservation) -> Option<f32>,
+) -> Vec<TimelinePoint> {
+    let start = match observations.first() {
+        Some(first) => first.info.time,
+        None => return Vec::new(),
+    };
+
+    let mut points: Vec<TimelinePoint> = Vec::new();
+    for (observation_index, observation) in observations.iter().enumerate() {
+        let value = match measure(observation) {
+            Some(value) => value,
+            None => continue,
+        };
+        let months = months_between(start, observation.info.time);
+        let rate = points.last().and_then(|previous| {
+            let elapsed = months - previous.months;
+            if elapsed > 0.0 {
+                Some(((value - previous.value) as f64 / elapsed) as f32)
+            } else {
+                None
+            }
+        });
+        points.push(TimelinePoint {
+            observation_index,
+            months,
+            value,
+            rate,
+        });
+    }
+    points
+}
+
+#[test]
+fn test_timeline_points() {
+    let observations: Vec<MoleObservation> = [(0, 2.0), (10, 0.0), (61, 3.0), (61, 3.5)]
+        .iter()
+        .map(|(days, diameter)| {
+            let mut info = crate::photo_set::PhotoInfo::new(
+                SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(days * 24 * 60 * 60),
+            );
+            info.mole_metrics.diameter = *diameter;
+            MoleObservation {
+                visit_path: Default::default(),
+                photo_id: *days as usize,
+                info,
+                calibration: Default::default(),
+                colour_matrix: None,
+            }
+        })
+        .collect();
+    let points = timeline_points(&observations, |observation| {
+        observation.info.mole_metrics.size()
+    });
+
+    assert_eq!(points.len(), 3);
+    assert_eq!(points[0].rate, None);
+    assert_eq!(points[1].observation_index, 2);
+    assert!((points[1].months - 2.0).abs() < 0.01);
+    assert!((points[1].rate.unwrap() - 0.5).abs() < 0.01);
+    // Same day photos cannot give a rate.
+    assert_eq!(points[2].rate, None);
+}